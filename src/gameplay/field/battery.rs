@@ -0,0 +1,200 @@
+﻿use bevy::prelude::*;
+
+use super::influence::Influence;
+use super::synergy::Synergies;
+use super::{CursorCell, FieldGrid, Machine, Tool, world_cell};
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+const DISCHARGE_RATE: f32 = 6.0;
+
+// Stabilizers top their cells up to the low end of the sweet spot from their store.
+const STABILIZER_FEED_RATE: f32 = 2.0;
+const STABILIZER_FEED_TARGET: f32 = 3.0;
+
+const GAUGE_HEIGHT: f32 = 0.8;
+
+// -----------------------------
+// Components
+// -----------------------------
+
+/// Aether held inside a machine rather than in the field.
+#[derive(Component, Clone, Copy)]
+pub(super) struct AetherStore {
+    pub(super) stored: f32,
+    pub(super) capacity: f32,
+    pub(super) discharging: bool,
+}
+
+impl AetherStore {
+    pub(super) fn new(capacity: f32) -> Self {
+        Self {
+            stored: 0.0,
+            capacity,
+            discharging: false,
+        }
+    }
+
+    pub(super) fn room(&self) -> f32 {
+        (self.capacity - self.stored).max(0.0)
+    }
+
+    pub(super) fn fill(&self) -> f32 {
+        if self.capacity > 0.0 {
+            (self.stored / self.capacity).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    pub(super) fn is_full(&self) -> bool {
+        self.room() <= f32::EPSILON
+    }
+}
+
+/// Child bar whose height follows the parent's store.
+#[derive(Component)]
+pub(super) struct FillGauge;
+
+pub(super) fn spawn_gauge(
+    parent: &mut ChildSpawnerCommands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    parent.spawn((
+        Mesh3d(meshes.add(Cuboid::new(0.08, GAUGE_HEIGHT, 0.08))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgb(0.95, 0.85, 0.3),
+            emissive: Color::srgb(0.6, 0.5, 0.1).into(),
+            unlit: true,
+            ..default()
+        })),
+        Transform::from_xyz(0.36, 0.0, 0.0).with_scale(Vec3::new(1.0, 0.0, 1.0)),
+        FillGauge,
+    ));
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+pub(super) fn toggle_discharge(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut q: Query<(&Transform, &mut AetherStore), With<Machine>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyD) {
+        return;
+    }
+
+    for (t, mut store) in &mut q {
+        if world_cell(t.translation) == (cursor.x, cursor.y) {
+            store.discharging = !store.discharging;
+        }
+    }
+}
+
+// -----------------------------
+// Simulation
+// -----------------------------
+
+/// Stores switched to discharging push aether into neighbouring machines that have room.
+/// A full store that isn't discharging just stops draining.
+pub(super) fn discharge_into_neighbors(
+    time: Res<Time>,
+    mut q: Query<(Entity, &Transform, &mut AetherStore), With<Machine>>,
+) {
+    let dt = time.delta_secs();
+
+    let sources: Vec<(Entity, (i32, i32))> = q
+        .iter()
+        .filter(|(_, _, s)| s.discharging && s.stored > 0.0)
+        .map(|(e, t, _)| (e, world_cell(t.translation)))
+        .collect();
+
+    for (src, (sx, sy)) in sources {
+        let mut budget = q.get(src).map(|(_, _, s)| s.stored).unwrap_or(0.0);
+        budget = budget.min(DISCHARGE_RATE * dt);
+        let mut sent = 0.0;
+
+        for (e, t, mut store) in &mut q {
+            if e == src || store.discharging || budget <= 0.0 {
+                continue;
+            }
+            let (x, y) = world_cell(t.translation);
+            if (x - sx).abs() > 1 || (y - sy).abs() > 1 {
+                continue;
+            }
+
+            let moved = store.room().min(budget);
+            store.stored += moved;
+            budget -= moved;
+            sent += moved;
+        }
+
+        if let Ok((_, _, mut store)) = q.get_mut(src) {
+            store.stored -= sent;
+        }
+    }
+}
+
+pub(super) fn stabilizers_feed_field(
+    time: Res<Time>,
+    mut grid: ResMut<FieldGrid>,
+    mut q: Query<
+        (
+            &Transform,
+            &Machine,
+            &Influence,
+            &Synergies,
+            &mut AetherStore,
+        ),
+        Without<super::overload::Disabled>,
+    >,
+) {
+    let dt = time.delta_secs();
+
+    for (t, m, inf, syn, mut store) in &mut q {
+        if m.kind != Tool::Stabilizer || store.stored <= 0.0 {
+            continue;
+        }
+
+        // the same footprint the stabilizer grows crystal over
+        let (gx, gy) = world_cell(t.translation);
+        for (xx, yy, w) in inf.cells(gx, gy, syn.radius(m)) {
+            if !grid.in_bounds(xx, yy) || store.stored <= 0.0 {
+                continue;
+            }
+            let idx = grid.idx(xx, yy);
+            let deficit = STABILIZER_FEED_TARGET - grid.aether[idx];
+            if deficit <= 0.0 {
+                continue;
+            }
+
+            let feed = deficit.min(STABILIZER_FEED_RATE * w * dt).min(store.stored);
+            grid.aether[idx] += feed;
+            store.stored -= feed;
+        }
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+pub(super) fn update_fill_gauges(
+    stores: Query<(&AetherStore, &Children), Changed<AetherStore>>,
+    mut gauges: Query<&mut Transform, With<FillGauge>>,
+) {
+    for (store, children) in &stores {
+        for child in children.iter() {
+            if let Ok(mut t) = gauges.get_mut(child) {
+                let fill = store.fill();
+                t.scale.y = fill;
+                t.translation.y = (fill - 1.0) * GAUGE_HEIGHT * 0.5;
+            }
+        }
+    }
+}
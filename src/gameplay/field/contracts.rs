@@ -0,0 +1,289 @@
+﻿use bevy::prelude::*;
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use super::economy::{Funds, Grade, Market};
+use super::inventory::{CRYSTAL, Inventory};
+use super::{Machine, Tool};
+use crate::state::InSession;
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+// Seed of contract generation, so the same run gets the same orders.
+const CONTRACT_SEED: u64 = 0x5eed_0042;
+
+const BUYERS: &[&str] = &[
+    "Glasswright Guild",
+    "Lumen Works",
+    "Aether Institute",
+    "Harbor Optics",
+    "Old Mill Co-op",
+];
+
+// What buyers ask for: graded crystal or refined goods.
+const WANTED: &[(Grade, &str)] = &[
+    (Grade::Clear, CRYSTAL),
+    (Grade::Flawless, CRYSTAL),
+    (Grade::Rough, "shard"),
+    (Grade::Rough, "lens"),
+    (Grade::Rough, "prism"),
+];
+
+// An order is worth this much at market base prices, and pays this multiple of it.
+const ORDER_VALUE: std::ops::Range<f32> = 40.0..140.0;
+const REWARD_MARKUP: std::ops::Range<f32> = 1.5..2.2;
+// Failing an order costs this fraction of its reward.
+const PENALTY_SHARE: f32 = 0.3;
+// Minutes to fill an order.
+const TIME_LIMIT: std::ops::Range<f32> = 5.0..12.0;
+
+const OFFER_INTERVAL: f32 = 45.0;
+// An offer that isn't taken is withdrawn after this long.
+const OFFER_SECS: f32 = 60.0;
+const MAX_ACTIVE: usize = 3;
+
+// -----------------------------
+// Resources + Components
+// -----------------------------
+
+/// An order for `amount` of `item`, to be filled from storage before time runs out.
+#[derive(Clone, Debug)]
+pub(super) struct Contract {
+    buyer: &'static str,
+    item: String,
+    amount: u32,
+    delivered: u32,
+    reward: f32,
+    penalty: f32,
+    /// Seconds left to fill it, or to accept it while it is on offer.
+    remaining: f32,
+    time_limit: f32,
+}
+
+impl Contract {
+    fn describe(&self) -> String {
+        format!(
+            "{} wants {} {} in {} (pays {:.0}, penalty {:.0})",
+            self.buyer,
+            self.amount,
+            self.item,
+            clock(self.time_limit),
+            self.reward,
+            self.penalty
+        )
+    }
+}
+
+fn clock(secs: f32) -> String {
+    let secs = secs.max(0.0) as u32;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+#[derive(Resource)]
+pub(super) struct Contracts {
+    offer: Option<Contract>,
+    active: Vec<Contract>,
+    rng: Pcg32,
+    next_offer: Timer,
+}
+
+impl Default for Contracts {
+    fn default() -> Self {
+        let mut next_offer = Timer::from_seconds(OFFER_INTERVAL, TimerMode::Repeating);
+        // the first offer comes early
+        next_offer.set_elapsed(next_offer.duration().mul_f32(0.8));
+        Self {
+            offer: None,
+            active: Vec::new(),
+            rng: Pcg32::seed_from_u64(CONTRACT_SEED),
+            next_offer,
+        }
+    }
+}
+
+impl Contracts {
+    fn generate(&mut self, market: &Market) -> Option<Contract> {
+        let buyer = *BUYERS.choose(&mut self.rng)?;
+        let &(grade, kind) = WANTED.choose(&mut self.rng)?;
+        let item = grade.item(kind);
+        let unit = market.base_price(&item)?;
+
+        let value = self.rng.random_range(ORDER_VALUE);
+        let amount = ((value / unit).round() as u32).max(1);
+        let reward = (amount as f32 * unit * self.rng.random_range(REWARD_MARKUP)).round();
+        let time_limit = (self.rng.random_range(TIME_LIMIT) * 60.0).round();
+
+        Some(Contract {
+            buyer,
+            item,
+            amount,
+            delivered: 0,
+            reward,
+            penalty: (reward * PENALTY_SHARE).round(),
+            remaining: OFFER_SECS,
+            time_limit,
+        })
+    }
+}
+
+#[derive(Component)]
+pub(super) struct ContractsText;
+
+// -----------------------------
+// Setup
+// -----------------------------
+
+pub(super) fn spawn_contracts_panel(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(16.0),
+            bottom: Val::Px(16.0),
+            max_width: Val::Px(460.0),
+            padding: UiRect::all(Val::Px(10.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        children![(
+            Text::new(""),
+            TextFont {
+                font_size: 13.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            ContractsText,
+        )],
+        DespawnOnExit(InSession),
+    ));
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+/// J accepts the contract on offer.
+pub(super) fn accept_contract(keys: Res<ButtonInput<KeyCode>>, mut contracts: ResMut<Contracts>) {
+    if !keys.just_pressed(KeyCode::KeyJ) {
+        return;
+    }
+    if contracts.active.len() >= MAX_ACTIVE {
+        info!("already working on {MAX_ACTIVE} contracts");
+        return;
+    }
+    let Some(mut c) = contracts.offer.take() else {
+        return;
+    };
+    c.remaining = c.time_limit;
+    info!("accepted: {}", c.describe());
+    contracts.active.push(c);
+}
+
+// -----------------------------
+// Simulation (fixed tick)
+// -----------------------------
+
+/// Posts new offers, counts down deadlines, and fills active contracts from storage
+/// chests, paying out rewards and charging penalties as they complete or lapse.
+pub(super) fn run_contracts(
+    time: Res<Time>,
+    market: Res<Market>,
+    mut funds: ResMut<Funds>,
+    mut contracts: ResMut<Contracts>,
+    mut chests: Query<(&Machine, &mut Inventory)>,
+) {
+    let dt = time.delta_secs();
+    let contracts = &mut *contracts;
+
+    if contracts.next_offer.tick(time.delta()).just_finished() && contracts.offer.is_none() {
+        contracts.offer = contracts.generate(&market);
+        if let Some(c) = &contracts.offer {
+            info!("new contract on offer (J to accept): {}", c.describe());
+        }
+    }
+    if let Some(c) = &mut contracts.offer {
+        c.remaining -= dt;
+        if c.remaining <= 0.0 {
+            contracts.offer = None;
+        }
+    }
+
+    // the most urgent contract gets first pick of what's in storage
+    contracts
+        .active
+        .sort_by(|a, b| a.remaining.total_cmp(&b.remaining));
+    for c in &mut contracts.active {
+        c.remaining -= dt;
+        for (m, mut inv) in &mut chests {
+            if m.kind != Tool::Chest {
+                continue;
+            }
+            let n = inv.count(&c.item).min(c.amount - c.delivered);
+            if n > 0 && inv.take(&c.item, n) {
+                c.delivered += n;
+            }
+        }
+    }
+
+    contracts.active.retain(|c| {
+        if c.delivered >= c.amount {
+            funds.0 += c.reward;
+            info!(
+                "{} paid {:.0} credits for {} {}",
+                c.buyer, c.reward, c.amount, c.item
+            );
+            false
+        } else if c.remaining <= 0.0 {
+            funds.0 -= c.penalty;
+            warn!(
+                "missed the {} order for {} {}: {:.0} credit penalty",
+                c.buyer, c.amount, c.item, c.penalty
+            );
+            false
+        } else {
+            true
+        }
+    });
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+pub(super) fn update_contracts_panel(
+    contracts: Res<Contracts>,
+    mut q: Query<&mut Text, With<ContractsText>>,
+) {
+    let Ok(mut text) = q.single_mut() else { return };
+
+    let mut lines = vec!["Contracts".to_string()];
+    for c in &contracts.active {
+        lines.push(format!(
+            "{}: {}/{} {}, {} left (pays {:.0}, penalty {:.0})",
+            c.buyer,
+            c.delivered,
+            c.amount,
+            c.item,
+            clock(c.remaining),
+            c.reward,
+            c.penalty
+        ));
+    }
+    if contracts.active.is_empty() {
+        lines.push("none active".to_string());
+    }
+    if let Some(c) = &contracts.offer {
+        lines.push(format!(
+            "Offer, J to accept ({} left): {}",
+            clock(c.remaining),
+            c.describe()
+        ));
+    }
+
+    let line = lines.join("\n");
+    if text.0 != line {
+        text.0 = line;
+    }
+}
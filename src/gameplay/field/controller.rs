@@ -0,0 +1,449 @@
+﻿use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::prelude::*;
+
+use super::hud::{HudPanel, spawn_readout};
+use super::machines::machine_def;
+use super::overload::Disabled;
+use super::script::{self, Builtin, Host, Program};
+use super::signals::{Gate, Sensor};
+use super::{CursorCell, Drive, FieldGrid, Machine, cell_world, world_cell};
+use crate::state::InSession;
+
+// How far from itself a controller can read the field, in cells.
+const READ_RANGE: i32 = 4;
+
+const DEFAULT_SOURCE: &str = "# set(0, 1) runs link 0 at full strength\n";
+
+// -----------------------------
+// Components + Resources
+// -----------------------------
+
+/// Programmable block. Its script runs once per tick within the tier's instruction
+/// budget and scales the machines it is linked to via `set(link, 0..1)`.
+#[derive(Component)]
+pub(super) struct Controller {
+    source: String,
+    program: Option<Program>,
+    memory: Vec<f32>,
+    /// Linked machines, addressed by index from the script.
+    pub(super) links: Vec<Entity>,
+    /// Last value set for each link; links start at full strength.
+    levels: Vec<f32>,
+    error: Option<String>,
+    cost: u32,
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        Self::new(DEFAULT_SOURCE.to_string())
+    }
+}
+
+impl Controller {
+    pub(super) fn new(source: String) -> Self {
+        let mut c = Self {
+            source: String::new(),
+            program: None,
+            memory: Vec::new(),
+            links: Vec::new(),
+            levels: Vec::new(),
+            error: None,
+            cost: 0,
+        };
+        c.set_source(source);
+        c
+    }
+
+    pub(super) fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Replaces and recompiles the script. A script that fails to compile leaves the
+    /// controller idle until it is fixed.
+    fn set_source(&mut self, source: String) {
+        match script::compile(&source) {
+            Ok(program) => {
+                self.memory = vec![0.0; program.var_count()];
+                self.program = Some(program);
+                self.error = None;
+            }
+            Err(e) => {
+                self.program = None;
+                self.error = Some(e.to_string());
+            }
+        }
+        self.source = source;
+    }
+
+    fn toggle_link(&mut self, target: Entity) -> bool {
+        if let Some(i) = self.links.iter().position(|&l| l == target) {
+            self.links.remove(i);
+            false
+        } else {
+            self.links.push(target);
+            true
+        }
+    }
+}
+
+/// Controller whose links are being edited with L.
+#[derive(Resource, Default)]
+pub(super) struct Linking(Option<Entity>);
+
+/// Present while a controller's script is open for editing; blocks the other
+/// keyboard controls so typing doesn't build or demolish anything.
+#[derive(Resource)]
+pub(super) struct ScriptEditor {
+    target: Entity,
+    buffer: String,
+    panel: Entity,
+}
+
+#[derive(Component)]
+pub(super) struct ControllerReadout;
+
+#[derive(Component)]
+pub(super) struct EditorText;
+
+struct ControllerHost<'a> {
+    grid: &'a FieldGrid,
+    origin: (i32, i32),
+    inputs: &'a [f32],
+    levels: &'a mut [f32],
+    time: f32,
+    dt: f32,
+}
+
+impl Host for ControllerHost<'_> {
+    fn call(&mut self, builtin: Builtin, args: &[f32]) -> f32 {
+        let link = |v: f32| (v >= 0.0).then_some(v as usize);
+        match builtin {
+            Builtin::Aether | Builtin::Crystal => {
+                let (dx, dy) = (args[0].round() as i32, args[1].round() as i32);
+                let (x, y) = (self.origin.0 + dx, self.origin.1 + dy);
+                if dx.abs().max(dy.abs()) > READ_RANGE || !self.grid.in_bounds(x, y) {
+                    return 0.0;
+                }
+                let i = self.grid.idx(x, y);
+                if builtin == Builtin::Aether {
+                    self.grid.aether[i]
+                } else {
+                    self.grid.crystal[i]
+                }
+            }
+            Builtin::Sensor => link(args[0])
+                .and_then(|n| self.inputs.get(n))
+                .copied()
+                .unwrap_or(0.0),
+            Builtin::Set => match link(args[0]).and_then(|n| self.levels.get_mut(n)) {
+                Some(level) => {
+                    *level = args[1].clamp(0.0, 1.0);
+                    *level
+                }
+                None => 0.0,
+            },
+            Builtin::Time => self.time,
+            Builtin::Dt => self.dt,
+            // pure math is handled by the interpreter itself
+            Builtin::Min | Builtin::Max | Builtin::Abs | Builtin::Clamp => 0.0,
+        }
+    }
+}
+
+// -----------------------------
+// Setup
+// -----------------------------
+
+pub(super) fn setup_readout(mut commands: Commands, panel: Res<HudPanel>) {
+    spawn_readout(&mut commands, &panel, ControllerReadout);
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+/// L on a controller starts linking from it; L on other machines then toggles their
+/// link, and L on the controller again (or an empty cell) finishes.
+pub(super) fn link_machines(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut linking: ResMut<Linking>,
+    mut controllers: Query<&mut Controller>,
+    machines: Query<(Entity, &Transform, &Machine)>,
+) {
+    if !keys.just_pressed(KeyCode::KeyL) {
+        return;
+    }
+
+    let under = machines
+        .iter()
+        .find(|(_, t, _)| world_cell(t.translation) == (cursor.x, cursor.y));
+
+    match (linking.0, under) {
+        (Some(from), Some((e, _, m))) if e != from => {
+            let Ok(mut c) = controllers.get_mut(from) else {
+                linking.0 = None;
+                return;
+            };
+            let name = machine_def(m.kind).map_or("machine", |d| d.name);
+            if c.toggle_link(e) {
+                info!("linked {name} as #{}", c.links.len() - 1);
+            } else {
+                info!("unlinked {name}");
+            }
+        }
+        (Some(_), _) => {
+            linking.0 = None;
+            info!("finished linking");
+        }
+        (None, Some((e, _, _))) if controllers.contains(e) => {
+            linking.0 = Some(e);
+            info!("linking: press L on machines to link or unlink them");
+        }
+        (None, _) => {}
+    }
+}
+
+/// Enter on a controller opens its script in the editor.
+pub(super) fn open_editor(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut commands: Commands,
+    editor: Option<Res<ScriptEditor>>,
+    controllers: Query<(Entity, &Transform, &Controller)>,
+) {
+    if editor.is_some() || !keys.just_pressed(KeyCode::Enter) {
+        return;
+    }
+    let Some((e, _, c)) = controllers
+        .iter()
+        .find(|(_, t, _)| world_cell(t.translation) == (cursor.x, cursor.y))
+    else {
+        return;
+    };
+
+    let panel = commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(16.0),
+                bottom: Val::Px(16.0),
+                width: Val::Px(520.0),
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.02, 0.02, 0.06, 0.9)),
+            children![(
+                Text::new(""),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                EditorText,
+            )],
+            DespawnOnExit(InSession),
+        ))
+        .id();
+
+    commands.insert_resource(ScriptEditor {
+        target: e,
+        buffer: c.source().to_string(),
+        panel,
+    });
+}
+
+/// Types into the open script. Esc compiles it into the controller and closes the editor.
+/// Reads keyboard messages every frame so the key that opened the editor isn't typed.
+pub(super) fn edit_script(
+    mut commands: Commands,
+    mut input: MessageReader<KeyboardInput>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    editor: Option<ResMut<ScriptEditor>>,
+    mut controllers: Query<&mut Controller>,
+) {
+    let Some(mut editor) = editor else {
+        input.clear();
+        return;
+    };
+
+    for ev in input.read() {
+        if !ev.state.is_pressed() {
+            continue;
+        }
+        match &ev.logical_key {
+            Key::Escape => {
+                if let Ok(mut c) = controllers.get_mut(editor.target) {
+                    c.set_source(std::mem::take(&mut editor.buffer));
+                    match &c.error {
+                        Some(e) => warn!("controller script: {e}"),
+                        None => info!("controller script compiled"),
+                    }
+                }
+                commands.entity(editor.panel).despawn();
+                commands.remove_resource::<ScriptEditor>();
+                // closing the editor shouldn't also open the pause menu
+                keys.clear_just_pressed(KeyCode::Escape);
+                return;
+            }
+            Key::Enter => editor.buffer.push('\n'),
+            Key::Tab => editor.buffer.push_str("  "),
+            Key::Space => editor.buffer.push(' '),
+            Key::Backspace => {
+                editor.buffer.pop();
+            }
+            Key::Character(s) => editor.buffer.push_str(s),
+            _ => {}
+        }
+    }
+}
+
+// -----------------------------
+// Simulation
+// -----------------------------
+
+/// Runs every powered controller's script and applies its link levels on top of
+/// the drive the targets already have. Runs after `update_drive`.
+pub(super) fn run_controllers(
+    time: Res<Time>,
+    grid: Res<FieldGrid>,
+    mut controllers: Query<(&Transform, &Machine, &Drive, &mut Controller), Without<Disabled>>,
+    sensors: Query<&Sensor>,
+    gates: Query<&Gate>,
+    mut targets: Query<&mut Drive, Without<Controller>>,
+) {
+    for (t, m, drive, mut c) in &mut controllers {
+        let c = &mut *c;
+        c.levels.resize(c.links.len(), 1.0);
+        if drive.0 <= 0.0 {
+            continue;
+        }
+
+        if let Some(program) = &c.program {
+            let inputs: Vec<f32> = c
+                .links
+                .iter()
+                .map(|&e| {
+                    sensors
+                        .get(e)
+                        .map(Sensor::output)
+                        .or_else(|_| gates.get(e).map(Gate::output))
+                        .unwrap_or(0.0)
+                })
+                .collect();
+            let mut host = ControllerHost {
+                grid: &grid,
+                origin: world_cell(t.translation),
+                inputs: &inputs,
+                levels: &mut c.levels,
+                time: time.elapsed_secs(),
+                dt: time.delta_secs(),
+            };
+            // the budget is the controller's "strength"
+            match script::run(program, &mut c.memory, &mut host, m.strength as u32) {
+                Ok(used) => {
+                    c.cost = used;
+                    c.error = None;
+                }
+                Err(e) => {
+                    c.cost = m.strength as u32;
+                    c.error = Some(e.to_string());
+                }
+            }
+        }
+
+        for (&e, &level) in c.links.iter().zip(&c.levels) {
+            if let Ok(mut target) = targets.get_mut(e) {
+                target.0 *= level;
+            }
+        }
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+/// Draws the links of the controller under the cursor, or of the one being linked.
+pub(super) fn draw_links(
+    mut gizmos: Gizmos,
+    cursor: Res<CursorCell>,
+    linking: Res<Linking>,
+    controllers: Query<(Entity, &Transform, &Controller)>,
+    transforms: Query<&Transform>,
+) {
+    for (e, t, c) in &controllers {
+        let here = world_cell(t.translation) == (cursor.x, cursor.y);
+        if !here && linking.0 != Some(e) {
+            continue;
+        }
+        let from = t.translation + Vec3::Y * 0.3;
+        for &l in &c.links {
+            if let Ok(lt) = transforms.get(l) {
+                gizmos.line(
+                    from,
+                    lt.translation + Vec3::Y * 0.3,
+                    Color::srgb(0.3, 1.0, 0.6),
+                );
+            }
+        }
+        if linking.0 == Some(e) {
+            let (x, y) = (cursor.x, cursor.y);
+            gizmos.line(from, cell_world(x, y) + Vec3::Y * 0.5, Color::WHITE);
+        }
+    }
+}
+
+pub(super) fn update_readout(
+    cursor: Res<CursorCell>,
+    controllers: Query<(&Transform, &Machine, &Controller)>,
+    mut readout: Query<&mut Text, With<ControllerReadout>>,
+) {
+    let Ok(mut text) = readout.single_mut() else {
+        return;
+    };
+
+    let under = controllers
+        .iter()
+        .find(|(t, _, _)| world_cell(t.translation) == (cursor.x, cursor.y));
+    let line = match under {
+        Some((_, m, c)) => match &c.error {
+            Some(e) => format!("Controller: {e}"),
+            None => format!(
+                "Controller: {} links, {}/{} ops",
+                c.links.len(),
+                c.cost,
+                m.strength as u32
+            ),
+        },
+        None => match controllers
+            .iter()
+            .filter(|(_, _, c)| c.error.is_some())
+            .count()
+        {
+            0 => String::new(),
+            n => format!("Controllers: {n} with errors"),
+        },
+    };
+    if text.0 != line {
+        text.0 = line;
+    }
+}
+
+pub(super) fn update_editor_text(
+    editor: Option<Res<ScriptEditor>>,
+    mut q: Query<&mut Text, With<EditorText>>,
+) {
+    let Some(editor) = editor else { return };
+    if !editor.is_changed() {
+        return;
+    }
+    let Ok(mut text) = q.single_mut() else { return };
+
+    // compile as you type so errors show up before Esc
+    let status = match script::compile(&editor.buffer) {
+        Ok(_) => "ok".to_string(),
+        Err(e) => e.to_string(),
+    };
+    text.0 = format!("{}_\n\n[{status}]  Esc: save and close", editor.buffer);
+}
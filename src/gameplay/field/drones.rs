@@ -0,0 +1,643 @@
+﻿use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use super::economy::Grade;
+use super::hud::{HudPanel, spawn_readout};
+use super::inventory::{CRYSTAL, Inventory};
+use super::logistics::is_recipe_input;
+use super::refining::{RecipeBook, Recipes, Refiner};
+use super::wear::Wear;
+use super::{
+    CursorCell, Drive, FieldGrid, Layout, Machine, Occupancy, SelectedTool, Tool, cell_world,
+    world_cell,
+};
+use crate::state::InSession;
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+// Cells per second.
+const DRONE_SPEED: f32 = 3.0;
+const DRONE_HEIGHT: f32 = 1.6;
+// Items a drone carries per trip.
+const DRONE_CARRY: u32 = 5;
+// Wear a drone works off per second once it reaches a machine.
+const DRONE_REPAIR_RATE: f32 = 8.0;
+
+// Jobs are planned on this interval, up to a cap so the queue doesn't grow unbounded.
+const PLAN_INTERVAL: f32 = 1.0;
+const MAX_QUEUED: usize = 16;
+// A cell needs this much crystal to be worth a harvesting trip.
+const HARVEST_MIN: f32 = 4.0;
+// Machines get a repair job once they are this worn.
+const REPAIR_AT: f32 = 50.0;
+
+// -----------------------------
+// Components + Resources
+// -----------------------------
+
+/// Blocks drones. Otherwise inert.
+#[derive(Component)]
+pub(super) struct Wall {
+    x: i32,
+    y: i32,
+}
+
+impl Wall {
+    pub(super) fn cell(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(super) enum Job {
+    /// Pick up crystal from a field cell and bring it to storage.
+    Harvest { x: i32, y: i32 },
+    /// Empty a refiner's products into storage.
+    Deliver { from: Entity },
+    /// Work off a machine's wear.
+    Repair { target: Entity },
+}
+
+/// Work waiting for a free drone, most urgent first.
+#[derive(Resource)]
+pub(super) struct JobQueue {
+    pub(super) jobs: VecDeque<Job>,
+    timer: Timer,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self {
+            jobs: VecDeque::new(),
+            timer: Timer::from_seconds(PLAN_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Cells drones can't pass: walls and machine footprints.
+#[derive(Resource, Default)]
+pub(super) struct NavGrid {
+    blocked: HashSet<(i32, i32)>,
+}
+
+#[derive(Component)]
+pub(super) struct Drone {
+    home: Entity,
+    cell: (i32, i32),
+    path: VecDeque<(i32, i32)>,
+    /// Progress towards the next cell on the path (0..1).
+    step: f32,
+    job: Option<Job>,
+    cargo: Option<(String, u32)>,
+    dropoff: Option<Entity>,
+}
+
+#[derive(Component)]
+pub(super) struct DroneReadout;
+
+// -----------------------------
+// Pathfinding
+// -----------------------------
+
+fn neighbours((x, y): (i32, i32)) -> [(i32, i32); 4] {
+    [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
+}
+
+impl NavGrid {
+    /// Free cells next to (x, y), where a drone stands to work on whatever is there.
+    fn around(&self, grid: &FieldGrid, cell: (i32, i32)) -> HashSet<(i32, i32)> {
+        neighbours(cell)
+            .into_iter()
+            .filter(|&(x, y)| grid.in_bounds(x, y) && !self.blocked.contains(&(x, y)))
+            .collect()
+    }
+
+    /// A* over the 4-connected grid to the nearest of `goals`. The path excludes `start`.
+    fn find_path(
+        &self,
+        grid: &FieldGrid,
+        start: (i32, i32),
+        goals: &HashSet<(i32, i32)>,
+    ) -> Option<VecDeque<(i32, i32)>> {
+        if goals.contains(&start) {
+            return Some(VecDeque::new());
+        }
+        let h = |c: (i32, i32)| {
+            goals
+                .iter()
+                .map(|g| (g.0 - c.0).abs() + (g.1 - c.1).abs())
+                .min()
+        };
+        h(start)?;
+
+        let mut open = BinaryHeap::new();
+        let mut came_from = HashMap::new();
+        let mut cost = HashMap::from([(start, 0)]);
+        open.push(Reverse((h(start)?, 0, start)));
+
+        while let Some(Reverse((_, g, c))) = open.pop() {
+            if goals.contains(&c) {
+                let mut path = VecDeque::from([c]);
+                let mut at = c;
+                while let Some(&prev) = came_from.get(&at) {
+                    if prev == start {
+                        break;
+                    }
+                    path.push_front(prev);
+                    at = prev;
+                }
+                return Some(path);
+            }
+            if g > cost[&c] {
+                continue;
+            }
+
+            for n in neighbours(c) {
+                if !grid.in_bounds(n.0, n.1) || self.blocked.contains(&n) {
+                    continue;
+                }
+                let ng = g + 1;
+                if cost.get(&n).is_none_or(|&old| ng < old) {
+                    cost.insert(n, ng);
+                    came_from.insert(n, c);
+                    open.push(Reverse((ng + h(n)?, ng, n)));
+                }
+            }
+        }
+        None
+    }
+}
+
+// -----------------------------
+// Setup
+// -----------------------------
+
+pub(super) fn setup_readout(mut commands: Commands, panel: Res<HudPanel>) {
+    spawn_readout(&mut commands, &panel, DroneReadout);
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+pub(super) fn place_wall(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    tool: Res<SelectedTool>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    occupancy: Occupancy,
+) {
+    if tool.0 != Tool::Wall || !keys.just_pressed(KeyCode::Space) {
+        return;
+    }
+    if !occupancy.is_free(cursor.x, cursor.y) {
+        return;
+    }
+
+    spawn_wall(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        cursor.x,
+        cursor.y,
+    );
+}
+
+pub(super) fn spawn_wall(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    x: i32,
+    y: i32,
+) -> Entity {
+    commands
+        .spawn((
+            Mesh3d(meshes.add(Cuboid::new(0.95, 1.0, 0.95))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::srgb(0.3, 0.3, 0.33),
+                perceptual_roughness: 0.95,
+                ..default()
+            })),
+            Transform::from_translation(cell_world(x, y) + Vec3::Y * 0.6),
+            Wall { x, y },
+            DespawnOnExit(InSession),
+        ))
+        .id()
+}
+
+pub(super) fn remove_wall(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut commands: Commands,
+    walls: Query<(Entity, &Wall)>,
+) {
+    if !keys.just_pressed(KeyCode::KeyX) {
+        return;
+    }
+
+    for (e, w) in &walls {
+        if w.cell() == (cursor.x, cursor.y) {
+            commands.entity(e).despawn();
+        }
+    }
+}
+
+// -----------------------------
+// Simulation (fixed tick)
+// -----------------------------
+
+pub(super) fn rebuild_nav(
+    layout: Res<Layout>,
+    mut nav: ResMut<NavGrid>,
+    machines: Query<&Transform, With<Machine>>,
+    walls: Query<&Wall>,
+) {
+    if !layout.is_changed() {
+        return;
+    }
+
+    nav.blocked = machines
+        .iter()
+        .map(|t| world_cell(t.translation))
+        .chain(walls.iter().map(Wall::cell))
+        .collect();
+}
+
+/// Refills the job queue: repairs first, then deliveries, then harvesting. Nothing
+/// gets queued twice, and nothing that needs storage is queued while there is none.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn plan_jobs(
+    time: Res<Time>,
+    grid: Res<FieldGrid>,
+    recipes: Res<Recipes>,
+    books: Res<Assets<RecipeBook>>,
+    mut queue: ResMut<JobQueue>,
+    drones: Query<&Drone>,
+    machines: Query<(Entity, &Transform, &Machine, &Wear)>,
+    refiners: Query<(Entity, &Refiner, &Inventory)>,
+) {
+    if !queue.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let book = books.get(&recipes.0);
+
+    let claimed: HashSet<Job> = queue
+        .jobs
+        .iter()
+        .copied()
+        .chain(drones.iter().filter_map(|d| d.job))
+        .collect();
+    let has_storage = machines.iter().any(|(_, _, m, _)| m.kind == Tool::Chest);
+
+    let mut planned = Vec::new();
+    for (e, _, _, wear) in &machines {
+        if wear.0 >= REPAIR_AT {
+            planned.push(Job::Repair { target: e });
+        }
+    }
+    if has_storage {
+        for (e, refiner, inv) in &refiners {
+            if inv
+                .iter()
+                .any(|(item, _)| !is_recipe_input(Some(refiner), book, item))
+            {
+                planned.push(Job::Deliver { from: e });
+            }
+        }
+
+        let mut rich: Vec<(f32, i32, i32)> = (0..grid.h)
+            .flat_map(|y| (0..grid.w).map(move |x| (x, y)))
+            .map(|(x, y)| (grid.crystal[grid.idx(x, y)], x, y))
+            .filter(|&(c, ..)| c >= HARVEST_MIN)
+            .collect();
+        rich.sort_by(|a, b| b.0.total_cmp(&a.0));
+        planned.extend(rich.into_iter().map(|(_, x, y)| Job::Harvest { x, y }));
+    }
+
+    for job in planned {
+        if queue.jobs.len() >= MAX_QUEUED {
+            break;
+        }
+        if !claimed.contains(&job) {
+            queue.jobs.push_back(job);
+        }
+    }
+}
+
+/// Powered drone bays keep `strength` drones in the air; drones whose bay is gone
+/// are recalled.
+pub(super) fn launch_drones(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    bays: Query<(Entity, &Transform, &Machine, &Drive)>,
+    drones: Query<(Entity, &Drone)>,
+) {
+    for (e, d) in &drones {
+        if !bays.contains(d.home) {
+            commands.entity(e).despawn();
+        }
+    }
+
+    for (bay, t, m, drive) in &bays {
+        if m.kind != Tool::DroneBay || drive.0 <= 0.0 {
+            continue;
+        }
+        let launched = drones.iter().filter(|(_, d)| d.home == bay).count();
+        if launched >= m.strength as usize {
+            continue;
+        }
+
+        let cell = world_cell(t.translation);
+        commands.spawn((
+            Mesh3d(meshes.add(Sphere::new(0.18))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::srgb(0.9, 0.95, 1.0),
+                emissive: Color::srgb(0.3, 0.5, 0.8).into(),
+                ..default()
+            })),
+            Transform::from_translation(cell_world(cell.0, cell.1) + Vec3::Y * DRONE_HEIGHT),
+            Drone {
+                home: bay,
+                cell,
+                path: VecDeque::new(),
+                step: 0.0,
+                job: None,
+                cargo: None,
+                dropoff: None,
+            },
+            DespawnOnExit(InSession),
+        ));
+    }
+}
+
+/// Moves drones along their paths and carries out their jobs on arrival.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn run_drones(
+    time: Res<Time>,
+    mut grid: ResMut<FieldGrid>,
+    nav: Res<NavGrid>,
+    recipes: Res<Recipes>,
+    books: Res<Assets<RecipeBook>>,
+    mut queue: ResMut<JobQueue>,
+    mut drones: Query<&mut Drone>,
+    machines: Query<(Entity, &Transform, &Machine)>,
+    mut inventories: Query<(&mut Inventory, Option<&Refiner>)>,
+    mut wears: Query<&mut Wear>,
+) {
+    let dt = time.delta_secs();
+    let book = books.get(&recipes.0);
+    let cell_of = |e: Entity| {
+        machines
+            .get(e)
+            .ok()
+            .map(|(_, t, _)| world_cell(t.translation))
+    };
+
+    for mut drone in &mut drones {
+        let drone = &mut *drone;
+
+        if !drone.path.is_empty() {
+            drone.step += DRONE_SPEED * dt;
+            while drone.step >= 1.0 {
+                let Some(next) = drone.path.pop_front() else {
+                    break;
+                };
+                drone.cell = next;
+                drone.step -= 1.0;
+            }
+            if !drone.path.is_empty() {
+                continue;
+            }
+            drone.step = 0.0;
+        }
+
+        // carrying something: head for storage, or unload on arrival
+        if let Some((item, n)) = drone.cargo.clone() {
+            match drone.dropoff {
+                Some(chest) => {
+                    let left = match inventories.get_mut(chest) {
+                        Ok((mut inv, _)) => n - inv.add(&item, n),
+                        Err(_) => n,
+                    };
+                    drone.dropoff = None;
+                    if left == 0 {
+                        drone.cargo = None;
+                        drone.job = None;
+                    } else {
+                        drone.cargo = Some((item, left));
+                    }
+                }
+                None => {
+                    let mut chests = HashMap::new();
+                    for (e, t, m) in &machines {
+                        let has_room = inventories.get(e).is_ok_and(|(inv, _)| inv.room() > 0);
+                        if m.kind == Tool::Chest && has_room {
+                            for c in nav.around(&grid, world_cell(t.translation)) {
+                                chests.entry(c).or_insert(e);
+                            }
+                        }
+                    }
+                    let goals = chests.keys().copied().collect();
+                    if let Some(path) = nav.find_path(&grid, drone.cell, &goals) {
+                        let end = path.back().copied().unwrap_or(drone.cell);
+                        drone.dropoff = chests.get(&end).copied();
+                        drone.path = path;
+                    }
+                }
+            }
+            continue;
+        }
+
+        match drone.job {
+            Some(Job::Harvest { x, y }) => {
+                if grid.in_bounds(x, y) {
+                    let i = grid.idx(x, y);
+                    let n = (grid.crystal[i].floor() as u32).min(DRONE_CARRY);
+                    grid.crystal[i] -= n as f32;
+                    if n > 0 {
+                        let item = Grade::from_purity(grid.purity[i]).item(CRYSTAL);
+                        drone.cargo = Some((item, n));
+                        continue;
+                    }
+                }
+                drone.job = None;
+            }
+            Some(Job::Deliver { from }) => {
+                if let Ok((mut inv, refiner)) = inventories.get_mut(from) {
+                    let item = inv
+                        .iter()
+                        .map(|(i, _)| i.to_string())
+                        .find(|i| !is_recipe_input(refiner, book, i));
+                    if let Some(item) = item {
+                        let n = inv.count(&item).min(DRONE_CARRY);
+                        inv.take(&item, n);
+                        drone.cargo = Some((item, n));
+                        continue;
+                    }
+                }
+                drone.job = None;
+            }
+            Some(Job::Repair { target }) => match wears.get_mut(target) {
+                Ok(mut wear) if wear.0 > 0.0 => {
+                    wear.0 = (wear.0 - DRONE_REPAIR_RATE * dt).max(0.0);
+                }
+                _ => drone.job = None,
+            },
+            None => {
+                // take the first job this drone can reach
+                while let Some(job) = queue.jobs.pop_front() {
+                    let goals = match job {
+                        Job::Harvest { x, y } if !nav.blocked.contains(&(x, y)) => {
+                            HashSet::from([(x, y)])
+                        }
+                        Job::Harvest { x, y } => nav.around(&grid, (x, y)),
+                        Job::Deliver { from: e } | Job::Repair { target: e } => {
+                            let Some(cell) = cell_of(e) else { continue };
+                            nav.around(&grid, cell)
+                        }
+                    };
+                    if let Some(path) = nav.find_path(&grid, drone.cell, &goals) {
+                        drone.path = path;
+                        drone.job = Some(job);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+/// Glides drones between the cells the fixed tick moves them through.
+pub(super) fn update_drone_visuals(mut drones: Query<(&Drone, &mut Transform)>) {
+    for (d, mut t) in &mut drones {
+        let from = cell_world(d.cell.0, d.cell.1);
+        let to = d.path.front().map_or(from, |&(x, y)| cell_world(x, y));
+        t.translation = from.lerp(to, d.step.min(1.0)) + Vec3::Y * DRONE_HEIGHT;
+    }
+}
+
+pub(super) fn update_readout(
+    queue: Res<JobQueue>,
+    drones: Query<&Drone>,
+    mut q: Query<&mut Text, With<DroneReadout>>,
+) {
+    let Ok(mut text) = q.single_mut() else { return };
+
+    let total = drones.iter().count();
+    let line = if total == 0 {
+        String::new()
+    } else {
+        let busy = drones.iter().filter(|d| d.job.is_some()).count();
+        format!(
+            "Drones: {busy}/{total} busy, {} jobs queued",
+            queue.jobs.len()
+        )
+    };
+    if text.0 != line {
+        text.0 = line;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    /// Builds the nav grid the way the game does, from spawned walls and machines.
+    fn nav(walls: &[(i32, i32)], machines: &[(i32, i32)]) -> NavGrid {
+        let mut world = World::new();
+        world.init_resource::<NavGrid>();
+        world.init_resource::<Layout>();
+        for &(x, y) in walls {
+            world.spawn(Wall { x, y });
+        }
+        for &(x, y) in machines {
+            world.spawn((
+                Machine {
+                    kind: Tool::Emitter,
+                    tier: 0,
+                    strength: 1.0,
+                    radius: 1,
+                },
+                Transform::from_translation(cell_world(x, y)),
+            ));
+        }
+        world.run_system_once(rebuild_nav).unwrap();
+        world.remove_resource::<NavGrid>().unwrap()
+    }
+
+    fn assert_walkable(nav: &NavGrid, start: (i32, i32), path: &VecDeque<(i32, i32)>) {
+        let mut at = start;
+        for &c in path {
+            assert_eq!(
+                (c.0 - at.0).abs() + (c.1 - at.1).abs(),
+                1,
+                "{at:?} -> {c:?}"
+            );
+            assert!(!nav.blocked.contains(&c), "path crosses {c:?}");
+            at = c;
+        }
+    }
+
+    #[test]
+    fn routes_around_walls_and_machines() {
+        // a wall down x = 3 with a machine at its end; the only gap is (3, 7)
+        let walls: Vec<_> = (0..6).map(|y| (3, y)).collect();
+        let nav = nav(&walls, &[(3, 6)]);
+        let grid = FieldGrid::new(8, 8);
+
+        let path = nav
+            .find_path(&grid, (0, 0), &HashSet::from([(6, 0)]))
+            .expect("a path through the gap");
+        assert_walkable(&nav, (0, 0), &path);
+        assert!(path.contains(&(3, 7)));
+        assert_eq!(path.back(), Some(&(6, 0)));
+        // down to the gap and back up: 10 cells each way
+        assert_eq!(path.len(), 20);
+    }
+
+    #[test]
+    fn picks_the_nearest_goal() {
+        let nav = nav(&[], &[]);
+        let grid = FieldGrid::new(8, 8);
+        let path = nav
+            .find_path(&grid, (4, 4), &HashSet::from([(0, 0), (4, 6)]))
+            .unwrap();
+        assert_eq!(path, VecDeque::from([(4, 5), (4, 6)]));
+    }
+
+    #[test]
+    fn gives_up_on_unreachable_targets() {
+        let nav = nav(&[(5, 6), (7, 6), (6, 5)], &[(6, 7)]);
+        let grid = FieldGrid::new(8, 8);
+        assert!(
+            nav.find_path(&grid, (0, 0), &HashSet::from([(6, 6)]))
+                .is_none()
+        );
+        assert!(
+            nav.find_path(&grid, (0, 0), &HashSet::from([(20, 20)]))
+                .is_none()
+        );
+        assert!(nav.find_path(&grid, (0, 0), &HashSet::new()).is_none());
+    }
+
+    #[test]
+    fn start_on_a_goal_needs_no_steps() {
+        let nav = nav(&[], &[]);
+        let grid = FieldGrid::new(8, 8);
+        let path = nav.find_path(&grid, (2, 2), &HashSet::from([(2, 2), (5, 5)]));
+        assert_eq!(path, Some(VecDeque::new()));
+    }
+}
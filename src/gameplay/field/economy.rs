@@ -0,0 +1,528 @@
+﻿use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use super::hud::{HudPanel, spawn_readout};
+use super::inventory::{CRYSTAL, Inventory};
+use super::logistics::is_recipe_input;
+use super::refining::{RecipeBook, Recipes, Refiner};
+use super::{CursorCell, FieldGrid, world_cell};
+use crate::state::InSession;
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+pub(super) const STARTING_FUNDS: f32 = 200.0;
+
+// Item types the market trades: what it pays for one rough unit when demand is
+// normal, and how many units sold at once halve that price.
+const BASE_PRICES: &[(&str, f32, f32)] = &[
+    (CRYSTAL, 1.0, 200.0),
+    ("shard", 4.0, 60.0),
+    ("lens", 12.0, 20.0),
+    ("prism", 30.0, 8.0),
+];
+
+// Seed of the market's random demand, so a run plays out the same given the same sales.
+const MARKET_SEED: u64 = 0x0c52_7a1f;
+
+// Time constants, in seconds, for sold volume to be forgotten and for spikes to fade.
+const RECOVERY_SECS: f32 = 90.0;
+const SPIKE_SECS: f32 = 30.0;
+// Chance per type per second of a demand spike, and how much it raises the price.
+const SPIKE_CHANCE: f32 = 0.004;
+const SPIKE_SIZE: std::ops::Range<f32> = 0.5..1.5;
+
+const HISTORY_INTERVAL: f32 = 2.0;
+const HISTORY_LEN: usize = 60;
+// Bars in the graph are full height at this multiple of the base price.
+const GRAPH_CEILING: f32 = 2.5;
+const GRAPH_HEIGHT: f32 = 36.0;
+
+// Purity a cell's crystal needs for each grade above rough.
+const CLEAR_PURITY: f32 = 0.6;
+const FLAWLESS_PURITY: f32 = 0.85;
+
+// How long a failed purchase stays on the readout.
+const NOTICE_SECS: f32 = 2.5;
+
+// -----------------------------
+// Grades
+// -----------------------------
+
+/// Quality of a crystal item. Graded items carry the grade as a prefix of their id
+/// ("clear crystal"); rough items have none, so they stay usable as recipe inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Grade {
+    Rough,
+    Clear,
+    Flawless,
+}
+
+impl Grade {
+    const GRADED: [Grade; 2] = [Grade::Clear, Grade::Flawless];
+
+    /// Grade of crystal grown at `purity` (0..1, see `FieldGrid::purity`).
+    pub(super) fn from_purity(purity: f32) -> Self {
+        if purity >= FLAWLESS_PURITY {
+            Grade::Flawless
+        } else if purity >= CLEAR_PURITY {
+            Grade::Clear
+        } else {
+            Grade::Rough
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Grade::Rough => "",
+            Grade::Clear => "clear",
+            Grade::Flawless => "flawless",
+        }
+    }
+
+    fn multiplier(self) -> f32 {
+        match self {
+            Grade::Rough => 1.0,
+            Grade::Clear => 1.6,
+            Grade::Flawless => 2.5,
+        }
+    }
+
+    /// Item id of `kind` at this grade.
+    pub(super) fn item(self, kind: &str) -> String {
+        match self {
+            Grade::Rough => kind.to_string(),
+            _ => format!("{} {kind}", self.prefix()),
+        }
+    }
+
+    /// Splits an item id into its grade and type.
+    pub(super) fn parse(item: &str) -> (Grade, &str) {
+        Self::GRADED
+            .into_iter()
+            .find_map(|g| {
+                let kind = item.strip_prefix(g.prefix())?.strip_prefix(' ')?;
+                Some((g, kind))
+            })
+            .unwrap_or((Grade::Rough, item))
+    }
+}
+
+// -----------------------------
+// Resources + Messages
+// -----------------------------
+
+/// The player's credits.
+#[derive(Resource)]
+pub(super) struct Funds(pub(super) f32);
+
+impl Default for Funds {
+    fn default() -> Self {
+        Self(STARTING_FUNDS)
+    }
+}
+
+/// Current demand for one item type.
+struct Quote {
+    kind: &'static str,
+    base: f32,
+    depth: f32,
+    /// Recently sold units, decaying as the market recovers.
+    glut: f32,
+    /// Extra demand on top of normal (0 = none), decaying after a spike.
+    spike: f32,
+    /// Rough-grade prices, oldest first.
+    history: VecDeque<f32>,
+}
+
+impl Quote {
+    fn price(&self) -> f32 {
+        self.base * (1.0 + self.spike) / (1.0 + self.glut / self.depth)
+    }
+}
+
+/// Buys items by type and grade. Prices fall as the player sells a type and recover
+/// over time, with occasional demand spikes drawn from a seeded RNG.
+#[derive(Resource)]
+pub(super) struct Market {
+    quotes: Vec<Quote>,
+    rng: Pcg32,
+    sample: Timer,
+    /// Bumped whenever the price history gains a sample.
+    samples: u64,
+}
+
+impl Default for Market {
+    fn default() -> Self {
+        Self::new(MARKET_SEED)
+    }
+}
+
+impl Market {
+    pub(super) fn new(seed: u64) -> Self {
+        Self {
+            quotes: BASE_PRICES
+                .iter()
+                .map(|&(kind, base, depth)| Quote {
+                    kind,
+                    base,
+                    depth,
+                    glut: 0.0,
+                    spike: 0.0,
+                    history: VecDeque::from([base]),
+                })
+                .collect(),
+            rng: Pcg32::seed_from_u64(seed),
+            sample: Timer::from_seconds(HISTORY_INTERVAL, TimerMode::Repeating),
+            samples: 0,
+        }
+    }
+
+    fn quote(&self, kind: &str) -> Option<&Quote> {
+        self.quotes.iter().find(|q| q.kind == kind)
+    }
+
+    /// What one unit of `item` sells for right now, or None if the market doesn't buy it.
+    pub(super) fn price(&self, item: &str) -> Option<f32> {
+        let (grade, kind) = Grade::parse(item);
+        self.quote(kind).map(|q| q.price() * grade.multiplier())
+    }
+
+    /// What one unit of `item` sells for under normal demand.
+    pub(super) fn base_price(&self, item: &str) -> Option<f32> {
+        let (grade, kind) = Grade::parse(item);
+        self.quote(kind).map(|q| q.base * grade.multiplier())
+    }
+
+    /// Sells `n` units of `item`, each one pushing the price down a little, and returns
+    /// the credits earned, or None if the market doesn't buy it.
+    pub(super) fn sell(&mut self, item: &str, n: u32) -> Option<f32> {
+        let (grade, kind) = Grade::parse(item);
+        let quote = self.quotes.iter_mut().find(|q| q.kind == kind)?;
+        let mut earned = 0.0;
+        for _ in 0..n {
+            earned += quote.price() * grade.multiplier();
+            quote.glut += 1.0;
+        }
+        Some(earned)
+    }
+
+    /// Advances recovery, decays and rolls demand spikes, and records history.
+    /// Returns the types whose demand just spiked.
+    fn step(&mut self, dt: f32) -> Vec<&'static str> {
+        let recover = (-dt / RECOVERY_SECS).exp();
+        let fade = (-dt / SPIKE_SECS).exp();
+        let mut spiked = Vec::new();
+        for q in &mut self.quotes {
+            q.glut *= recover;
+            q.spike *= fade;
+            if self.rng.random::<f32>() < SPIKE_CHANCE * dt {
+                q.spike += self.rng.random_range(SPIKE_SIZE);
+                spiked.push(q.kind);
+            }
+        }
+
+        if self
+            .sample
+            .tick(std::time::Duration::from_secs_f32(dt))
+            .just_finished()
+        {
+            for q in &mut self.quotes {
+                let price = q.price();
+                q.history.push_back(price);
+                if q.history.len() > HISTORY_LEN {
+                    q.history.pop_front();
+                }
+            }
+            self.samples += 1;
+        }
+        spiked
+    }
+}
+
+/// A build was refused for lack of credits.
+#[derive(Message, Debug, Clone, Copy)]
+pub(super) struct InsufficientFunds {
+    pub name: &'static str,
+    pub price: f32,
+}
+
+#[derive(Component)]
+pub(super) struct FundsReadout;
+
+/// Panel with a price history row per item type.
+#[derive(Component)]
+pub(super) struct MarketGraph;
+
+#[derive(Component)]
+pub(super) struct PriceLabel(usize);
+
+/// One sample in a type's history; `slot` 0 is the oldest.
+#[derive(Component)]
+pub(super) struct PriceBar {
+    kind: usize,
+    slot: usize,
+}
+
+// -----------------------------
+// Setup
+// -----------------------------
+
+pub(super) fn setup_readout(mut commands: Commands, panel: Res<HudPanel>) {
+    spawn_readout(&mut commands, &panel, FundsReadout);
+}
+
+pub(super) fn spawn_market_graph(mut commands: Commands) {
+    let colors = [
+        Color::srgb(0.75, 0.75, 1.0),
+        Color::srgb(0.5, 0.9, 0.9),
+        Color::srgb(0.95, 0.8, 0.35),
+        Color::srgb(0.95, 0.5, 0.9),
+    ];
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(16.0),
+                bottom: Val::Px(16.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.0),
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            MarketGraph,
+            DespawnOnExit(InSession),
+        ))
+        .with_children(|panel| {
+            for (kind, _) in BASE_PRICES.iter().enumerate() {
+                let color = colors[kind % colors.len()];
+                panel.spawn((
+                    Text::new(""),
+                    TextFont {
+                        font_size: 12.0,
+                        ..default()
+                    },
+                    TextColor(color),
+                    PriceLabel(kind),
+                ));
+                panel
+                    .spawn(Node {
+                        height: Val::Px(GRAPH_HEIGHT),
+                        align_items: AlignItems::FlexEnd,
+                        column_gap: Val::Px(1.0),
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        for slot in 0..HISTORY_LEN {
+                            row.spawn((
+                                Node {
+                                    width: Val::Px(3.0),
+                                    height: Val::Percent(0.0),
+                                    ..default()
+                                },
+                                BackgroundColor(color),
+                                PriceBar { kind, slot },
+                            ));
+                        }
+                    });
+            }
+        });
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+/// S sells what the machine under the cursor holds (everything the market buys and
+/// the machine would give up), or else the crystal in the cell itself.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn sell_at_cursor(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    recipes: Res<Recipes>,
+    books: Res<Assets<RecipeBook>>,
+    mut market: ResMut<Market>,
+    mut funds: ResMut<Funds>,
+    mut grid: ResMut<FieldGrid>,
+    mut stores: Query<(&Transform, &mut Inventory, Option<&Refiner>)>,
+) {
+    if !keys.just_pressed(KeyCode::KeyS) {
+        return;
+    }
+    let book = books.get(&recipes.0);
+
+    let store = stores
+        .iter_mut()
+        .find(|(t, ..)| world_cell(t.translation) == (cursor.x, cursor.y));
+
+    let mut sold = Vec::new();
+    if let Some((_, mut inv, refiner)) = store {
+        let offered: Vec<(String, u32)> = inv
+            .iter()
+            .filter(|(i, _)| !is_recipe_input(refiner, book, i))
+            .map(|(i, n)| (i.to_string(), n))
+            .collect();
+        for (item, n) in offered {
+            if market.price(&item).is_some() {
+                inv.take(&item, n);
+                sold.push((item, n));
+            }
+        }
+    } else if grid.in_bounds(cursor.x, cursor.y) {
+        let i = grid.idx(cursor.x, cursor.y);
+        let n = grid.crystal[i].floor() as u32;
+        let item = Grade::from_purity(grid.purity[i]).item(CRYSTAL);
+        if n > 0 && market.price(&item).is_some() {
+            grid.crystal[i] -= n as f32;
+            sold.push((item, n));
+        }
+    }
+
+    if sold.is_empty() {
+        info!("nothing here the market buys");
+        return;
+    }
+    for (item, n) in sold {
+        let Some(earned) = market.sell(&item, n) else {
+            continue;
+        };
+        funds.0 += earned;
+        info!("sold {n} {item} for {earned:.0} credits");
+    }
+}
+
+/// K shows or hides the price graph.
+pub(super) fn toggle_market_graph(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut q: Query<&mut Visibility, With<MarketGraph>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyK) {
+        return;
+    }
+    for mut vis in &mut q {
+        vis.toggle_visible_hidden();
+    }
+}
+
+// -----------------------------
+// Simulation (fixed tick)
+// -----------------------------
+
+pub(super) fn update_market(time: Res<Time>, mut market: ResMut<Market>) {
+    for kind in market.step(time.delta_secs()) {
+        info!("demand for {kind} is spiking");
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+pub(super) fn update_readout(
+    time: Res<Time>,
+    funds: Res<Funds>,
+    mut refused: MessageReader<InsufficientFunds>,
+    mut notice: Local<Option<(String, Timer)>>,
+    mut q: Query<(&mut Text, &mut TextColor), With<FundsReadout>>,
+) {
+    if let Some(r) = refused.read().last() {
+        warn!(
+            "{} costs {:.0} credits, you have {:.0}",
+            r.name, r.price, funds.0
+        );
+        *notice = Some((
+            format!("need {:.0} for {}", r.price, r.name),
+            Timer::from_seconds(NOTICE_SECS, TimerMode::Once),
+        ));
+    }
+    if let Some((_, timer)) = notice.as_mut()
+        && timer.tick(time.delta()).is_finished()
+    {
+        *notice = None;
+    }
+
+    let Ok((mut text, mut color)) = q.single_mut() else {
+        return;
+    };
+    let line = match notice.as_ref() {
+        Some((msg, _)) => format!("Credits: {:.0} ({msg})", funds.0),
+        None => format!("Credits: {:.0}", funds.0),
+    };
+    if text.0 != line {
+        text.0 = line;
+        color.0 = if notice.is_some() {
+            Color::srgb(1.0, 0.4, 0.3)
+        } else {
+            Color::WHITE
+        };
+    }
+}
+
+/// Redraws the price graph whenever the history gains a sample.
+pub(super) fn update_market_graph(
+    market: Res<Market>,
+    mut drawn: Local<Option<u64>>,
+    mut labels: Query<(&PriceLabel, &mut Text)>,
+    mut bars: Query<(&PriceBar, &mut Node)>,
+) {
+    if *drawn == Some(market.samples) {
+        return;
+    }
+    *drawn = Some(market.samples);
+
+    for (label, mut text) in &mut labels {
+        let q = &market.quotes[label.0];
+        let change = (q.price() / q.base - 1.0) * 100.0;
+        text.0 = format!("{} {:.2} ({change:+.0}%)", q.kind, q.price());
+    }
+    for (bar, mut node) in &mut bars {
+        let q = &market.quotes[bar.kind];
+        // right-align the history so the newest sample is always the last bar
+        let offset = HISTORY_LEN - q.history.len();
+        let height = bar
+            .slot
+            .checked_sub(offset)
+            .and_then(|i| q.history.get(i))
+            .map_or(0.0, |p| (p / (q.base * GRAPH_CEILING)).min(1.0) * 100.0);
+        node.height = Val::Percent(height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a market for 1000 simulated seconds with a sale partway through, returning
+    /// every type's price after each step and the spikes it rolled.
+    fn play(seed: u64) -> (Vec<f32>, Vec<&'static str>) {
+        let mut market = Market::new(seed);
+        let mut prices = Vec::new();
+        let mut spikes = Vec::new();
+        for i in 0..2000 {
+            if i == 500 {
+                market.sell("lens", 10);
+            }
+            spikes.extend(market.step(0.5));
+            prices.extend(
+                BASE_PRICES
+                    .iter()
+                    .map(|(kind, ..)| market.price(kind).unwrap()),
+            );
+        }
+        (prices, spikes)
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_prices() {
+        let (prices, spikes) = play(MARKET_SEED);
+        assert!(!spikes.is_empty(), "the run should roll some spikes");
+        assert_eq!((prices, spikes), play(MARKET_SEED));
+        assert_ne!(play(MARKET_SEED).0, play(MARKET_SEED + 1).0);
+    }
+}
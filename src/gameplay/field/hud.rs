@@ -0,0 +1,43 @@
+﻿use bevy::prelude::*;
+
+use crate::state::InSession;
+
+/// Column of status lines in the top-right corner. Each system that reports
+/// something spawns its own text line into it with [`spawn_readout`].
+#[derive(Resource)]
+pub(super) struct HudPanel(Entity);
+
+pub(super) fn spawn_hud(mut commands: Commands) {
+    let panel = commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(16.0),
+                top: Val::Px(16.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            DespawnOnExit(InSession),
+        ))
+        .id();
+
+    commands.insert_resource(HudPanel(panel));
+}
+
+pub(super) fn spawn_readout(commands: &mut Commands, panel: &HudPanel, marker: impl Component) {
+    let line = commands
+        .spawn((
+            Text::new(""),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            marker,
+        ))
+        .id();
+    commands.entity(panel.0).add_child(line);
+}
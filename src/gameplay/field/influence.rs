@@ -0,0 +1,217 @@
+﻿use std::f32::consts::FRAC_PI_4;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::machines::machine_def;
+use super::{CELL_SPACING, CursorCell, SelectedTool, Tool, cell_world};
+
+// Half-width of a cone, in radians.
+const CONE_HALF_ANGLE: f32 = 0.55;
+// How far a cell centre may sit off the beam and still count as on a line.
+const LINE_HALF_WIDTH: f32 = 0.75;
+
+// -----------------------------
+// Components + Resources
+// -----------------------------
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum Shape {
+    #[default]
+    Circle,
+    /// Every cell within `radius` along both axes; stabilizers start with it.
+    Square,
+    Cone,
+    Line,
+    Ring,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum Falloff {
+    #[default]
+    Flat,
+    Linear,
+    Quadratic,
+    Smooth,
+}
+
+/// Where and how strongly a machine acts on the cells around it.
+/// `facing` is in eighths of a turn, 0 pointing along +x.
+#[derive(Component, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub(super) struct Influence {
+    pub(super) shape: Shape,
+    pub(super) falloff: Falloff,
+    pub(super) facing: u8,
+}
+
+/// Orientation and shape the next placed machine will get.
+#[derive(Resource, Default)]
+pub(super) struct Placement(pub(super) Influence);
+
+impl Shape {
+    /// Footprint a machine of this kind gets unless the player picks another.
+    pub(super) fn default_for(kind: Tool) -> Self {
+        match kind {
+            Tool::Stabilizer => Shape::Square,
+            _ => Shape::Circle,
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Shape::Circle => Shape::Square,
+            Shape::Square => Shape::Cone,
+            Shape::Cone => Shape::Line,
+            Shape::Line => Shape::Ring,
+            Shape::Ring => Shape::Circle,
+        }
+    }
+}
+
+impl Falloff {
+    fn next(self) -> Self {
+        match self {
+            Falloff::Flat => Falloff::Linear,
+            Falloff::Linear => Falloff::Quadratic,
+            Falloff::Quadratic => Falloff::Smooth,
+            Falloff::Smooth => Falloff::Flat,
+        }
+    }
+
+    fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Falloff::Flat => 1.0,
+            Falloff::Linear => 1.0 - t,
+            Falloff::Quadratic => (1.0 - t) * (1.0 - t),
+            Falloff::Smooth => 1.0 - t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+impl Influence {
+    pub(super) fn for_tool(kind: Tool) -> Self {
+        Self {
+            shape: Shape::default_for(kind),
+            ..default()
+        }
+    }
+
+    fn direction(&self) -> Vec2 {
+        let a = self.facing as f32 * FRAC_PI_4;
+        Vec2::new(a.cos(), a.sin())
+    }
+
+    /// Weight (0..1) of the cell at offset (dx, dy), or `None` if it is outside the shape.
+    pub(super) fn weight(&self, dx: i32, dy: i32, radius: i32) -> Option<f32> {
+        let r = radius as f32;
+        let offset = Vec2::new(dx as f32, dy as f32);
+        let dist = offset.length();
+
+        let t = match self.shape {
+            Shape::Circle => (dist <= r).then_some(dist / r.max(1.0))?,
+            Shape::Square => {
+                let reach = dx.abs().max(dy.abs());
+                (reach <= radius).then_some(reach as f32 / r.max(1.0))?
+            }
+            Shape::Cone => {
+                let inside = dist <= r
+                    && (dist == 0.0 || offset.angle_to(self.direction()).abs() <= CONE_HALF_ANGLE);
+                inside.then_some(dist / r.max(1.0))?
+            }
+            Shape::Line => {
+                let dir = self.direction();
+                let along = offset.dot(dir);
+                let across = offset.perp_dot(dir).abs();
+                let inside = along >= 0.0 && along <= r && across <= LINE_HALF_WIDTH;
+                inside.then_some(along / r.max(1.0))?
+            }
+            // the ring is the outermost band; falloff runs from its inner edge outward
+            Shape::Ring => {
+                let inside = dist <= r && dist >= r - 1.0;
+                inside.then_some(dist - (r - 1.0))?
+            }
+        };
+
+        Some(self.falloff.apply(t))
+    }
+
+    /// Cells reached from (gx, gy) with their weights. Callers do the bounds check.
+    pub(super) fn cells(
+        &self,
+        gx: i32,
+        gy: i32,
+        radius: i32,
+    ) -> impl Iterator<Item = (i32, i32, f32)> {
+        let this = *self;
+        ((gy - radius)..=(gy + radius)).flat_map(move |yy| {
+            ((gx - radius)..=(gx + radius))
+                .filter_map(move |xx| this.weight(xx - gx, yy - gy, radius).map(|w| (xx, yy, w)))
+        })
+    }
+}
+
+/// Neighbouring cell on the side `facing` points to, snapped to the nearest of the four.
+pub(super) fn cardinal_step(facing: u8, x: i32, y: i32) -> (i32, i32) {
+    match (facing / 2) % 4 {
+        0 => (x + 1, y),
+        1 => (x, y + 1),
+        2 => (x - 1, y),
+        _ => (x, y - 1),
+    }
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+/// R rotates the next placement, F cycles its shape and G its falloff.
+pub(super) fn placement_input(keys: Res<ButtonInput<KeyCode>>, mut placement: ResMut<Placement>) {
+    let inf = &mut placement.0;
+    if keys.just_pressed(KeyCode::KeyR) {
+        inf.facing = (inf.facing + 1) % 8;
+    }
+    if keys.just_pressed(KeyCode::KeyF) {
+        inf.shape = inf.shape.next();
+    }
+    if keys.just_pressed(KeyCode::KeyG) {
+        inf.falloff = inf.falloff.next();
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+/// Outlines the cells the selected machine would reach if placed at the cursor.
+pub(super) fn draw_placement_preview(
+    mut gizmos: Gizmos,
+    cursor: Res<CursorCell>,
+    tool: Res<SelectedTool>,
+    placement: Res<Placement>,
+) {
+    let Some(def) = machine_def(tool.0) else {
+        return;
+    };
+    let radius = def.tier(0).radius;
+    if radius == 0 {
+        return;
+    }
+
+    let inf = placement.0;
+    for (x, y, w) in inf.cells(cursor.x, cursor.y, radius) {
+        let pos = cell_world(x, y) + Vec3::Y * 0.12;
+        gizmos.rect(
+            Isometry3d::new(pos, Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2)),
+            Vec2::splat(CELL_SPACING * 0.9),
+            def.color.with_alpha(0.25 + 0.75 * w),
+        );
+    }
+
+    if !matches!(inf.shape, Shape::Circle | Shape::Square) {
+        let from = cell_world(cursor.x, cursor.y) + Vec3::Y * 0.7;
+        let d = inf.direction();
+        let to = from + Vec3::new(d.x, 0.0, d.y) * CELL_SPACING * 1.5;
+        gizmos.arrow(from, to, Color::WHITE);
+    }
+}
@@ -0,0 +1,72 @@
+﻿use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Item id of crystal. Wherever it is an input it can also be drawn straight
+/// from the field, one unit per unit of cell crystal.
+pub(super) const CRYSTAL: &str = "crystal";
+
+/// Items held by a machine, counted by id, up to `capacity` items in total.
+#[derive(Component, Clone, Debug, Default, Serialize, Deserialize)]
+pub(super) struct Inventory {
+    items: BTreeMap<String, u32>,
+    capacity: u32,
+}
+
+impl Inventory {
+    pub(super) fn new(capacity: u32) -> Self {
+        Self {
+            items: BTreeMap::new(),
+            capacity,
+        }
+    }
+
+    pub(super) fn count(&self, item: &str) -> u32 {
+        self.items.get(item).copied().unwrap_or(0)
+    }
+
+    pub(super) fn total(&self) -> u32 {
+        self.items.values().sum()
+    }
+
+    pub(super) fn room(&self) -> u32 {
+        self.capacity.saturating_sub(self.total())
+    }
+
+    pub(super) fn set_capacity(&mut self, capacity: u32) {
+        self.capacity = capacity;
+    }
+
+    /// Adds as many of `n` as fit and returns how many that was.
+    pub(super) fn add(&mut self, item: &str, n: u32) -> u32 {
+        let n = n.min(self.room());
+        if n > 0 {
+            *self.items.entry(item.to_string()).or_default() += n;
+        }
+        n
+    }
+
+    /// Takes exactly `n`, or nothing if there aren't that many.
+    pub(super) fn take(&mut self, item: &str, n: u32) -> bool {
+        let Some(have) = self.items.get_mut(item) else {
+            return n == 0;
+        };
+        if *have < n {
+            return false;
+        }
+        *have -= n;
+        if *have == 0 {
+            self.items.remove(item);
+        }
+        true
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.items.iter().map(|(k, &v)| (k.as_str(), v))
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
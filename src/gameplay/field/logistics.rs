@@ -0,0 +1,374 @@
+﻿use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+
+use super::influence::{Influence, Placement, cardinal_step};
+use super::inventory::Inventory;
+use super::overload::Disabled;
+use super::refining::{RecipeBook, Recipes, Refiner};
+use super::{
+    CELL_SPACING, CursorCell, Drive, Machine, Occupancy, SelectedTool, Tool, cell_world, world_cell,
+};
+use crate::state::InSession;
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+// Belt speeds in cells per second; [ ] on a conveyor steps through them.
+const CONVEYOR_SPEEDS: [f32; 3] = [1.0, 2.0, 4.0];
+
+// -----------------------------
+// Components
+// -----------------------------
+
+/// Belt segment carrying at most one item towards the cell it faces.
+/// `facing` is in eighths of a turn like [`Influence`], snapped to the nearest side.
+#[derive(Component)]
+pub(super) struct Conveyor {
+    x: i32,
+    y: i32,
+    pub(super) facing: u8,
+    pub(super) speed: f32,
+    /// Item on the belt and how far along the segment it is (0..1).
+    pub(super) item: Option<(String, f32)>,
+}
+
+impl Conveyor {
+    pub(super) fn cell(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    fn ahead(&self) -> (i32, i32) {
+        cardinal_step(self.facing, self.x, self.y)
+    }
+}
+
+/// Swings one item at a time from the cell behind it to the cell ahead.
+#[derive(Component, Default)]
+pub(super) struct Inserter {
+    swing: f32,
+}
+
+#[derive(Component)]
+pub(super) struct BeltItem;
+
+/// What may go in and come out of a machine's inventory: refiners only take their
+/// recipe's inputs and only give up everything else; chests take and give anything.
+pub(super) fn is_recipe_input(
+    refiner: Option<&Refiner>,
+    book: Option<&RecipeBook>,
+    item: &str,
+) -> bool {
+    refiner
+        .and_then(|r| r.recipe.as_deref())
+        .and_then(|id| book?.get(id))
+        .is_some_and(|r| r.inputs.iter().any(|(i, _)| i == item))
+}
+
+fn accepts(refiner: Option<&Refiner>, book: Option<&RecipeBook>, item: &str) -> bool {
+    refiner.is_none() || is_recipe_input(refiner, book, item)
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+#[allow(clippy::too_many_arguments)]
+pub(super) fn place_conveyor(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    tool: Res<SelectedTool>,
+    placement: Res<Placement>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    occupancy: Occupancy,
+) {
+    if tool.0 != Tool::Conveyor || !keys.just_pressed(KeyCode::Space) {
+        return;
+    }
+    if !occupancy.is_free(cursor.x, cursor.y) {
+        return;
+    }
+
+    spawn_conveyor(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        cursor.x,
+        cursor.y,
+        placement.0.facing,
+        CONVEYOR_SPEEDS[0],
+        None,
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) fn spawn_conveyor(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    x: i32,
+    y: i32,
+    facing: u8,
+    speed: f32,
+    item: Option<String>,
+) -> Entity {
+    // snap to a side so the belt lines up with the cell it feeds
+    let facing = (facing / 2) * 2 % 8;
+    let yaw = -f32::from(facing / 2) * FRAC_PI_2;
+
+    commands
+        .spawn((
+            Mesh3d(meshes.add(Cuboid::new(0.9, 0.06, 0.5))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::srgb(0.25, 0.25, 0.28),
+                perceptual_roughness: 0.8,
+                ..default()
+            })),
+            Transform::from_translation(cell_world(x, y) + Vec3::Y * 0.13)
+                .with_rotation(Quat::from_rotation_y(yaw)),
+            Conveyor {
+                x,
+                y,
+                facing,
+                speed,
+                item: item.map(|i| (i, 0.0)),
+            },
+            DespawnOnExit(InSession),
+        ))
+        .with_children(|parent| {
+            // the direction stripe, then the item riding on the belt
+            parent.spawn((
+                Mesh3d(meshes.add(Cuboid::new(0.2, 0.02, 0.08))),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color: Color::srgb(0.9, 0.8, 0.2),
+                    unlit: true,
+                    ..default()
+                })),
+                Transform::from_xyz(0.3, 0.04, 0.0),
+            ));
+            parent.spawn((
+                Mesh3d(meshes.add(Cuboid::new(0.22, 0.22, 0.22))),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color: Color::srgb(0.85, 0.85, 1.0),
+                    emissive: Color::srgb(0.2, 0.2, 0.3).into(),
+                    ..default()
+                })),
+                Transform::from_xyz(0.0, 0.15, 0.0),
+                Visibility::Hidden,
+                BeltItem,
+            ));
+        })
+        .id()
+}
+
+pub(super) fn remove_conveyor(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut commands: Commands,
+    conveyors: Query<(Entity, &Conveyor)>,
+) {
+    if !keys.just_pressed(KeyCode::KeyX) {
+        return;
+    }
+
+    for (e, c) in &conveyors {
+        if c.cell() == (cursor.x, cursor.y) {
+            commands.entity(e).despawn();
+        }
+    }
+}
+
+/// [ ] slow down or speed up the conveyor under the cursor.
+pub(super) fn configure_conveyor(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut conveyors: Query<&mut Conveyor>,
+) {
+    let step: isize = if keys.just_pressed(KeyCode::BracketLeft) {
+        -1
+    } else if keys.just_pressed(KeyCode::BracketRight) {
+        1
+    } else {
+        return;
+    };
+
+    for mut c in &mut conveyors {
+        if c.cell() != (cursor.x, cursor.y) {
+            continue;
+        }
+        let i = CONVEYOR_SPEEDS
+            .iter()
+            .position(|&s| s >= c.speed)
+            .unwrap_or(0) as isize;
+        let next = (i + step).clamp(0, CONVEYOR_SPEEDS.len() as isize - 1) as usize;
+        c.speed = CONVEYOR_SPEEDS[next];
+        info!("conveyor speed {:.0} cells/s", c.speed);
+    }
+}
+
+// -----------------------------
+// Simulation (fixed tick)
+// -----------------------------
+
+/// Advances items along belts. An item at the end of its segment moves onto the
+/// next belt if that one is empty, or into the machine ahead if it will take it.
+pub(super) fn move_conveyors(
+    time: Res<Time>,
+    recipes: Res<Recipes>,
+    books: Res<Assets<RecipeBook>>,
+    mut conveyors: Query<(Entity, &mut Conveyor)>,
+    mut machines: Query<(&Transform, &mut Inventory, Option<&Refiner>), With<Machine>>,
+) {
+    let dt = time.delta_secs();
+    let book = books.get(&recipes.0);
+
+    let belts: HashMap<(i32, i32), Entity> = conveyors.iter().map(|(e, c)| (c.cell(), e)).collect();
+
+    let finished: Vec<(Entity, (i32, i32))> = conveyors
+        .iter_mut()
+        .filter_map(|(e, mut c)| {
+            let speed = c.speed;
+            let (_, progress) = c.item.as_mut()?;
+            *progress = (*progress + speed * dt).min(1.0);
+            (*progress >= 1.0).then(|| (e, c.ahead()))
+        })
+        .collect();
+
+    for (from, ahead) in finished {
+        let Ok((_, c)) = conveyors.get(from) else {
+            continue;
+        };
+        let Some((item, _)) = c.item.clone() else {
+            continue;
+        };
+
+        let delivered = if let Some(&next) = belts.get(&ahead) {
+            match conveyors.get_mut(next) {
+                Ok((_, mut n)) if n.item.is_none() => {
+                    n.item = Some((item, 0.0));
+                    true
+                }
+                _ => false,
+            }
+        } else {
+            machines
+                .iter_mut()
+                .find(|(t, ..)| world_cell(t.translation) == ahead)
+                .is_some_and(|(_, mut inv, refiner)| {
+                    accepts(refiner, book, &item) && inv.add(&item, 1) == 1
+                })
+        };
+
+        if delivered && let Ok((_, mut c)) = conveyors.get_mut(from) {
+            c.item = None;
+        }
+    }
+}
+
+/// Each inserter moves one item per swing from the belt or machine behind it to the
+/// belt or machine ahead, at `strength` swings per second scaled by its drive.
+pub(super) fn run_inserters(
+    time: Res<Time>,
+    recipes: Res<Recipes>,
+    books: Res<Assets<RecipeBook>>,
+    mut inserters: Query<
+        (&Transform, &Machine, &Drive, &Influence, &mut Inserter),
+        Without<Disabled>,
+    >,
+    mut conveyors: Query<&mut Conveyor>,
+    mut stores: Query<(&Transform, &mut Inventory, Option<&Refiner>), With<Machine>>,
+) {
+    let dt = time.delta_secs();
+    let book = books.get(&recipes.0);
+
+    for (t, m, drive, inf, mut inserter) in &mut inserters {
+        inserter.swing = (inserter.swing + m.strength * drive.0 * dt).min(1.0);
+        if inserter.swing < 1.0 {
+            continue;
+        }
+
+        let (x, y) = world_cell(t.translation);
+        let from = cardinal_step(inf.facing + 4, x, y);
+        let to = cardinal_step(inf.facing, x, y);
+
+        // what could be picked up behind: the belt's item, or anything the machine gives up
+        let offered: Vec<String> = if let Some(c) = conveyors.iter().find(|c| c.cell() == from) {
+            c.item.iter().map(|(i, _)| i.clone()).collect()
+        } else if let Some((_, inv, refiner)) = stores
+            .iter()
+            .find(|(t, ..)| world_cell(t.translation) == from)
+        {
+            inv.iter()
+                .filter(|(i, _)| !is_recipe_input(refiner, book, i))
+                .map(|(i, _)| i.to_string())
+                .collect()
+        } else {
+            continue;
+        };
+
+        // the first item the cell ahead will take
+        let item = if let Some(c) = conveyors.iter().find(|c| c.cell() == to) {
+            offered.into_iter().find(|_| c.item.is_none())
+        } else if let Some((_, inv, refiner)) = stores
+            .iter()
+            .find(|(t, ..)| world_cell(t.translation) == to)
+        {
+            offered
+                .into_iter()
+                .find(|i| inv.room() > 0 && accepts(refiner, book, i))
+        } else {
+            None
+        };
+        let Some(item) = item else {
+            continue;
+        };
+
+        // pick up
+        if let Some(mut c) = conveyors.iter_mut().find(|c| c.cell() == from) {
+            c.item = None;
+        } else if let Some((_, mut inv, _)) = stores
+            .iter_mut()
+            .find(|(t, ..)| world_cell(t.translation) == from)
+        {
+            inv.take(&item, 1);
+        }
+        // drop off
+        if let Some(mut c) = conveyors.iter_mut().find(|c| c.cell() == to) {
+            c.item = Some((item, 0.0));
+        } else if let Some((_, mut inv, _)) = stores
+            .iter_mut()
+            .find(|(t, ..)| world_cell(t.translation) == to)
+        {
+            inv.add(&item, 1);
+        }
+        inserter.swing = 0.0;
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+pub(super) fn update_belt_items(
+    conveyors: Query<(&Conveyor, &Children), Changed<Conveyor>>,
+    mut items: Query<(&mut Transform, &mut Visibility), With<BeltItem>>,
+) {
+    for (c, children) in &conveyors {
+        for child in children.iter() {
+            let Ok((mut t, mut vis)) = items.get_mut(child) else {
+                continue;
+            };
+            match &c.item {
+                Some((_, progress)) => {
+                    *vis = Visibility::Inherited;
+                    t.translation.x = (progress - 0.5) * CELL_SPACING;
+                }
+                None => *vis = Visibility::Hidden,
+            }
+        }
+    }
+}
@@ -0,0 +1,453 @@
+﻿use bevy::picking::hover::Hovered;
+use bevy::prelude::*;
+
+use super::battery::{self, AetherStore};
+use super::controller::Controller;
+use super::influence::Influence;
+use super::inventory::Inventory;
+use super::logistics::Inserter;
+use super::power::{self, PowerCell};
+use super::refining::{self, RecipeBook, Recipes, Refiner};
+use super::research::Research;
+use super::signals::{Gate, Sensor};
+use super::synergy::{Bonus, Synergy};
+use super::waveform::Waveform;
+use super::wear::Wear;
+use crate::state::InSession;
+use crate::ui::tooltip::HoverTooltip;
+
+use super::{CursorCell, FieldGrid, Machine, Tool, cell_world, world_cell};
+
+// Upgrades are paid for with crystal from the cells around the machine.
+const MIN_COST_RADIUS: i32 = 2;
+
+// -----------------------------
+// Definitions
+// -----------------------------
+
+/// One step on a machine's upgrade path.
+///
+/// `strength` is field strength for emitters/sinks, conversion rate for stabilizers,
+/// output for generators, the per-tick instruction budget for controllers, wear
+/// repaired per second for maintenance bays, work speed for refiners, swings per
+/// second for inserters and drones kept flying for drone bays. `capacity` sizes the
+/// aether, power or item store, if the machine has one.
+pub(super) struct TierDef {
+    pub(super) cost: f32,
+    pub(super) strength: f32,
+    pub(super) radius: i32,
+    pub(super) efficiency: f32,
+    pub(super) capacity: f32,
+}
+
+pub(super) struct MachineDef {
+    pub(super) name: &'static str,
+    /// Credits to build one.
+    pub(super) price: f32,
+    pub(super) color: Color,
+    pub(super) height: f32,
+    pub(super) tiers: &'static [TierDef],
+    /// Bonuses for neighbours of particular kinds, stacking per neighbour.
+    pub(super) synergies: &'static [Synergy],
+}
+
+impl MachineDef {
+    pub(super) fn tier(&self, tier: u8) -> &TierDef {
+        &self.tiers[(tier as usize).min(self.tiers.len() - 1)]
+    }
+
+    pub(super) fn max_tier(&self) -> u8 {
+        (self.tiers.len() - 1) as u8
+    }
+}
+
+const fn tier(cost: f32, strength: f32, radius: i32, efficiency: f32, capacity: f32) -> TierDef {
+    TierDef {
+        cost,
+        strength,
+        radius,
+        efficiency,
+        capacity,
+    }
+}
+
+static EMITTER: MachineDef = MachineDef {
+    name: "Emitter",
+    price: 20.0,
+    color: Color::srgb(0.2, 0.9, 0.9),
+    height: 1.1,
+    tiers: &[
+        tier(0.0, 8.0, 3, 1.0, 0.0),
+        tier(15.0, 11.0, 3, 1.15, 0.0),
+        tier(40.0, 14.0, 4, 1.3, 0.0),
+    ],
+    synergies: &[Synergy {
+        with: Tool::Pipe,
+        bonus: Bonus::Range(1),
+    }],
+};
+
+static SINK: MachineDef = MachineDef {
+    name: "Sink",
+    price: 20.0,
+    color: Color::srgb(0.95, 0.25, 0.3),
+    height: 0.9,
+    tiers: &[
+        tier(0.0, -8.0, 3, 1.0, 60.0),
+        tier(15.0, -11.0, 3, 1.15, 100.0),
+        tier(40.0, -14.0, 4, 1.3, 160.0),
+    ],
+    synergies: &[Synergy {
+        with: Tool::Sink,
+        bonus: Bonus::Strength(0.15),
+    }],
+};
+
+static STABILIZER: MachineDef = MachineDef {
+    name: "Stabilizer",
+    price: 35.0,
+    color: Color::srgb(0.75, 0.75, 1.0),
+    height: 1.3,
+    tiers: &[
+        tier(0.0, 1.2, 2, 1.0, 10.0),
+        tier(20.0, 1.6, 2, 1.2, 16.0),
+        tier(50.0, 2.1, 3, 1.4, 24.0),
+    ],
+    synergies: &[Synergy {
+        with: Tool::Stabilizer,
+        bonus: Bonus::SweetSpot(0.5),
+    }],
+};
+
+static GENERATOR: MachineDef = MachineDef {
+    name: "Generator",
+    price: 25.0,
+    color: Color::srgb(1.0, 0.55, 0.1),
+    height: 0.9,
+    tiers: &[tier(0.0, 10.0, 0, 1.0, 0.0), tier(25.0, 16.0, 0, 1.0, 0.0)],
+    synergies: &[Synergy {
+        with: Tool::Generator,
+        bonus: Bonus::Strength(0.1),
+    }],
+};
+
+static POWER_CELL: MachineDef = MachineDef {
+    name: "Power Cell",
+    price: 30.0,
+    color: Color::srgb(0.35, 0.9, 0.35),
+    height: 0.8,
+    tiers: &[
+        tier(0.0, 0.0, 0, 1.0, 120.0),
+        tier(20.0, 0.0, 0, 1.0, 240.0),
+    ],
+    synergies: &[],
+};
+
+static SENSOR: MachineDef = MachineDef {
+    name: "Sensor",
+    price: 5.0,
+    color: Color::srgb(0.9, 0.9, 0.9),
+    height: 0.6,
+    tiers: &[tier(0.0, 0.0, 0, 1.0, 0.0)],
+    synergies: &[],
+};
+
+static LOGIC: MachineDef = MachineDef {
+    name: "Logic Gate",
+    price: 5.0,
+    color: Color::srgb(0.6, 0.3, 0.9),
+    height: 0.6,
+    tiers: &[tier(0.0, 0.0, 0, 1.0, 0.0)],
+    synergies: &[],
+};
+
+static CONTROLLER: MachineDef = MachineDef {
+    name: "Controller",
+    price: 40.0,
+    color: Color::srgb(0.3, 1.0, 0.6),
+    height: 0.7,
+    tiers: &[
+        tier(0.0, 200.0, 0, 1.0, 0.0),
+        tier(30.0, 600.0, 0, 1.0, 0.0),
+    ],
+    synergies: &[],
+};
+
+static MAINTENANCE_BAY: MachineDef = MachineDef {
+    name: "Maintenance Bay",
+    price: 45.0,
+    color: Color::srgb(0.9, 0.85, 0.4),
+    height: 0.8,
+    tiers: &[tier(0.0, 1.5, 2, 1.0, 0.0), tier(30.0, 3.0, 3, 1.2, 0.0)],
+    synergies: &[],
+};
+
+static REFINER: MachineDef = MachineDef {
+    name: "Refiner",
+    price: 60.0,
+    color: Color::srgb(0.8, 0.5, 0.35),
+    height: 0.9,
+    tiers: &[tier(0.0, 1.0, 1, 1.0, 20.0), tier(35.0, 1.6, 1, 1.2, 40.0)],
+    synergies: &[],
+};
+
+static CHEST: MachineDef = MachineDef {
+    name: "Storage Chest",
+    price: 15.0,
+    color: Color::srgb(0.55, 0.4, 0.25),
+    height: 0.7,
+    tiers: &[tier(0.0, 0.0, 0, 1.0, 50.0), tier(20.0, 0.0, 0, 1.0, 120.0)],
+    synergies: &[],
+};
+
+static INSERTER: MachineDef = MachineDef {
+    name: "Inserter",
+    price: 10.0,
+    color: Color::srgb(0.4, 0.6, 0.95),
+    height: 0.6,
+    tiers: &[tier(0.0, 1.0, 0, 1.0, 0.0), tier(15.0, 2.5, 0, 1.2, 0.0)],
+    synergies: &[],
+};
+
+static DRONE_BAY: MachineDef = MachineDef {
+    name: "Drone Bay",
+    price: 80.0,
+    color: Color::srgb(0.35, 0.5, 0.75),
+    height: 0.7,
+    tiers: &[tier(0.0, 2.0, 0, 1.0, 0.0), tier(30.0, 4.0, 0, 1.2, 0.0)],
+    synergies: &[],
+};
+
+/// Conduits (pipes, power lines, signal wires, conveyors) and walls are not machines
+/// and have no definition.
+pub(super) fn machine_def(kind: Tool) -> Option<&'static MachineDef> {
+    match kind {
+        Tool::Emitter => Some(&EMITTER),
+        Tool::Sink => Some(&SINK),
+        Tool::Stabilizer => Some(&STABILIZER),
+        Tool::Generator => Some(&GENERATOR),
+        Tool::PowerCell => Some(&POWER_CELL),
+        Tool::Sensor => Some(&SENSOR),
+        Tool::Logic => Some(&LOGIC),
+        Tool::Controller => Some(&CONTROLLER),
+        Tool::MaintenanceBay => Some(&MAINTENANCE_BAY),
+        Tool::Refiner => Some(&REFINER),
+        Tool::Chest => Some(&CHEST),
+        Tool::Inserter => Some(&INSERTER),
+        Tool::DroneBay => Some(&DRONE_BAY),
+        Tool::Pipe | Tool::PowerLine | Tool::SignalWire | Tool::Conveyor | Tool::Wall => None,
+    }
+}
+
+// -----------------------------
+// Spawning
+// -----------------------------
+
+/// Spawns a machine of `kind` at `tier` on cell (x, y), with whatever stores its kind needs.
+pub(super) fn spawn_machine(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    kind: Tool,
+    tier: u8,
+    x: i32,
+    y: i32,
+) -> Entity {
+    let def = machine_def(kind).expect("conduits are not machines");
+    let t = def.tier(tier);
+
+    let mesh = meshes.add(Cuboid::new(0.55, 0.8, 0.55));
+    let mat = materials.add(StandardMaterial {
+        base_color: def.color,
+        emissive: tier_emissive(def.color, tier),
+        ..default()
+    });
+
+    let mut machine = commands.spawn((
+        Mesh3d(mesh),
+        MeshMaterial3d(mat),
+        Transform::from_translation(cell_world(x, y) + Vec3::Y * def.height)
+            .with_scale(tier_scale(tier)),
+        Machine {
+            kind,
+            tier,
+            strength: t.strength,
+            radius: t.radius,
+        },
+        Influence::for_tool(kind),
+        Hovered(false),
+        HoverTooltip(def.name.into()),
+        DespawnOnExit(InSession),
+    ));
+
+    match kind {
+        Tool::Sink | Tool::Stabilizer => {
+            machine
+                .insert(AetherStore::new(t.capacity))
+                .with_children(|parent| battery::spawn_gauge(parent, meshes, materials));
+        }
+        Tool::PowerCell => {
+            machine.insert(power::power_cell(t.capacity));
+        }
+        Tool::Emitter => {
+            machine.insert(Waveform::default());
+        }
+        Tool::Sensor => {
+            machine.insert(Sensor::default());
+        }
+        Tool::Logic => {
+            machine.insert(Gate::default());
+        }
+        Tool::Controller => {
+            machine.insert(Controller::default());
+        }
+        Tool::Chest => {
+            machine.insert(Inventory::new(t.capacity as u32));
+        }
+        Tool::Inserter => {
+            machine.insert(Inserter::default());
+        }
+        Tool::Refiner => {
+            machine
+                .insert((Refiner::default(), Inventory::new(t.capacity as u32)))
+                .with_children(|parent| refining::spawn_progress_bar(parent, meshes, materials));
+        }
+        _ => {}
+    }
+
+    machine.id()
+}
+
+// -----------------------------
+// Upgrades
+// -----------------------------
+
+#[allow(clippy::type_complexity)]
+pub(super) fn upgrade_machine(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    research: Res<Research>,
+    mut grid: ResMut<FieldGrid>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut q: Query<(
+        &mut Machine,
+        &mut Transform,
+        &MeshMaterial3d<StandardMaterial>,
+        Option<&mut AetherStore>,
+        Option<&mut PowerCell>,
+        Option<&mut Inventory>,
+    )>,
+) {
+    if !keys.just_pressed(KeyCode::KeyU) {
+        return;
+    }
+
+    for (mut m, mut t, mat, store, cell, inv) in &mut q {
+        if world_cell(t.translation) != (cursor.x, cursor.y) {
+            continue;
+        }
+        let Some(def) = machine_def(m.kind) else {
+            continue;
+        };
+        if m.tier >= def.max_tier() {
+            info!("{} is already at max tier", def.name);
+            continue;
+        }
+
+        let next = m.tier + 1;
+        if next > research.max_tier() {
+            info!("tier {} upgrades need research (T to browse)", next + 1);
+            continue;
+        }
+        let nt = def.tier(next);
+        if !grid.take_crystal_around(cursor.x, cursor.y, m.radius.max(MIN_COST_RADIUS), nt.cost) {
+            info!("{} upgrade needs {:.0} crystal nearby", def.name, nt.cost);
+            continue;
+        }
+
+        m.tier = next;
+        m.strength = nt.strength;
+        m.radius = nt.radius;
+        if let Some(mut store) = store {
+            store.capacity = nt.capacity;
+        }
+        if let Some(mut cell) = cell {
+            cell.set_capacity(nt.capacity);
+        }
+        if let Some(mut inv) = inv {
+            inv.set_capacity(nt.capacity as u32);
+        }
+
+        t.scale = tier_scale(next);
+        if let Some(mat) = materials.get_mut(&mat.0) {
+            mat.emissive = tier_emissive(def.color, next);
+        }
+        info!("{} upgraded to tier {}", def.name, next + 1);
+    }
+}
+
+// -----------------------------
+// Visuals
+// -----------------------------
+
+pub(super) fn tier_scale(tier: u8) -> Vec3 {
+    Vec3::splat(1.0 + 0.15 * tier as f32)
+}
+
+pub(super) fn tier_emissive(color: Color, tier: u8) -> LinearRgba {
+    LinearRgba::from(color) * (0.25 + 0.35 * tier as f32)
+}
+
+/// Keeps each machine's hover text in line with its state.
+#[allow(clippy::type_complexity)]
+pub(super) fn update_tooltips(
+    recipes: Res<Recipes>,
+    books: Res<Assets<RecipeBook>>,
+    mut q: Query<(
+        &Machine,
+        &Wear,
+        Option<&Refiner>,
+        Option<&Inventory>,
+        &mut HoverTooltip,
+    )>,
+) {
+    let book = books.get(&recipes.0);
+
+    for (m, wear, refiner, inv, mut tooltip) in &mut q {
+        let Some(def) = machine_def(m.kind) else {
+            continue;
+        };
+
+        let mut text = format!("{} (tier {})", def.name, m.tier + 1);
+        if wear.is_broken() {
+            text.push_str("\nBROKEN - press M to repair");
+        } else {
+            text.push_str(&format!(
+                "\nWear {:.0}%, efficiency {:.0}%",
+                wear.0,
+                wear.factor() * 100.0
+            ));
+        }
+        if let Some(refiner) = refiner {
+            let recipe = refiner
+                .recipe
+                .as_deref()
+                .map(|id| book.and_then(|b| b.get(id)).map_or(id, |r| r.name.as_str()));
+            match recipe {
+                Some(name) => text.push_str(&format!(
+                    "\nMaking {name}: {:.0}%",
+                    refiner.progress * 100.0
+                )),
+                None => text.push_str("\nNo recipe (V to choose)"),
+            }
+        }
+        if let Some(inv) = inv.filter(|inv| !inv.is_empty()) {
+            let items: Vec<String> = inv.iter().map(|(id, n)| format!("{n} {id}")).collect();
+            text.push_str(&format!("\nHolds {}", items.join(", ")));
+        }
+
+        if tooltip.0 != text {
+            tooltip.0 = text.into();
+        }
+    }
+}
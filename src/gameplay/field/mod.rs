@@ -0,0 +1,957 @@
+﻿use bevy::ecs::schedule::ScheduleLabel;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+use crate::state::{GameState, InSession, LoadingChecks, toggle_pause};
+
+pub struct FieldTestPlugin;
+
+mod battery;
+mod contracts;
+mod controller;
+mod drones;
+mod economy;
+mod hud;
+mod influence;
+mod inventory;
+mod logistics;
+mod machines;
+mod offline;
+mod overload;
+mod pipes;
+mod power;
+mod refining;
+mod research;
+mod save;
+mod scenario;
+mod script;
+mod signals;
+mod speed;
+mod synergy;
+mod tutorial;
+mod waveform;
+mod wear;
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+const W: i32 = 24;
+const H: i32 = 24;
+const CELL_SPACING: f32 = 1.0;
+
+const DIFFUSION: f32 = 6.0;
+const DECAY: f32 = 0.35;
+const MAX_AETHER: f32 = 10.0;
+// Aether range in which stabilizers turn aether into crystal.
+const SWEET_SPOT: (f32, f32) = (3.0, 7.5);
+
+// -----------------------------
+// Resources + Components
+// -----------------------------
+
+#[derive(Resource)]
+struct FieldGrid {
+    w: i32,
+    h: i32,
+    aether: Vec<f32>,
+    crystal: Vec<f32>,
+    /// How close to the middle of the sweet spot each cell's crystal grew (0..1);
+    /// decides its grade when sold.
+    purity: Vec<f32>,
+    pressure: Vec<f32>,
+}
+
+impl FieldGrid {
+    fn new(w: i32, h: i32) -> Self {
+        let n = (w * h) as usize;
+        Self {
+            w,
+            h,
+            aether: vec![0.0; n],
+            crystal: vec![0.0; n],
+            purity: vec![0.0; n],
+            pressure: vec![0.0; n],
+        }
+    }
+
+    fn idx(&self, x: i32, y: i32) -> usize {
+        (y * self.w + x) as usize
+    }
+
+    fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.w && y < self.h
+    }
+
+    /// Removes `amount` crystal from the cells within `r` of (x, y), drawing from each
+    /// cell in proportion to what it holds. Takes nothing if there is not enough.
+    fn take_crystal_around(&mut self, x: i32, y: i32, r: i32, amount: f32) -> bool {
+        let mut cells = Vec::new();
+        let mut total = 0.0;
+        for yy in (y - r)..=(y + r) {
+            for xx in (x - r)..=(x + r) {
+                if !self.in_bounds(xx, yy) || (xx - x).pow(2) + (yy - y).pow(2) > r * r {
+                    continue;
+                }
+                let i = self.idx(xx, yy);
+                total += self.crystal[i];
+                cells.push(i);
+            }
+        }
+
+        if total < amount {
+            return false;
+        }
+        if amount > 0.0 {
+            let keep = 1.0 - amount / total;
+            for i in cells {
+                self.crystal[i] *= keep;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Component)]
+struct Cell {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Component)]
+struct CellMat(Handle<StandardMaterial>);
+
+#[derive(Component)]
+struct CursorViz;
+
+#[derive(Resource, Clone, Copy)]
+struct CursorCell {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum Tool {
+    Emitter,
+    Sink,
+    Stabilizer,
+    Pipe,
+    Generator,
+    PowerCell,
+    PowerLine,
+    Sensor,
+    Logic,
+    SignalWire,
+    Controller,
+    MaintenanceBay,
+    Refiner,
+    Chest,
+    Conveyor,
+    Inserter,
+    Wall,
+    DroneBay,
+}
+
+impl Tool {
+    /// Every tool in selection order; Tab cycles through them.
+    const ALL: [Tool; 18] = [
+        Tool::Emitter,
+        Tool::Sink,
+        Tool::Stabilizer,
+        Tool::Pipe,
+        Tool::Generator,
+        Tool::PowerCell,
+        Tool::PowerLine,
+        Tool::Sensor,
+        Tool::Logic,
+        Tool::SignalWire,
+        Tool::Controller,
+        Tool::MaintenanceBay,
+        Tool::Refiner,
+        Tool::Chest,
+        Tool::Conveyor,
+        Tool::Inserter,
+        Tool::Wall,
+        Tool::DroneBay,
+    ];
+}
+
+#[derive(Resource)]
+struct SelectedTool(Tool);
+
+#[derive(Component)]
+struct ToolReadout;
+
+#[derive(Component, Clone, Copy)]
+#[require(
+    overload::Integrity,
+    power::Powered,
+    power::Output,
+    influence::Influence,
+    signals::Receiver,
+    synergy::Synergies,
+    wear::Wear,
+    Drive
+)]
+struct Machine {
+    kind: Tool,
+    tier: u8,
+    strength: f32,
+    radius: i32,
+}
+
+/// Fraction of its strength a machine delivers this tick, combining power supply,
+/// wear, waveform and signal control. Recomputed by `update_drive` before the field step.
+#[derive(Component, Clone, Copy)]
+struct Drive(f32);
+
+impl Default for Drive {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Everything that claims a grid cell, for placement checks.
+#[derive(SystemParam)]
+struct Occupancy<'w, 's> {
+    machines: Query<'w, 's, &'static Transform, With<Machine>>,
+    pipes: Query<'w, 's, &'static pipes::Pipe>,
+    lines: Query<'w, 's, &'static power::PowerLine>,
+    wires: Query<'w, 's, &'static signals::SignalWire>,
+    conveyors: Query<'w, 's, &'static logistics::Conveyor>,
+    walls: Query<'w, 's, &'static drones::Wall>,
+}
+
+impl Occupancy<'_, '_> {
+    fn is_free(&self, x: i32, y: i32) -> bool {
+        let on = |c: (i32, i32)| c == (x, y);
+        !(self.machines.iter().any(|t| on(world_cell(t.translation)))
+            || self.pipes.iter().any(|p| on(p.cell()))
+            || self.lines.iter().any(|l| on(l.cell()))
+            || self.wires.iter().any(|w| on(w.cell()))
+            || self.conveyors.iter().any(|c| on(c.cell()))
+            || self.walls.iter().any(|w| on(w.cell())))
+    }
+}
+
+/// Touched whenever a building or conduit is placed or removed. Networks, power grids,
+/// signal networks, synergies and the drone nav grid rebuild when it has changed
+/// since they last ran, however long that was.
+#[derive(Resource, Default)]
+struct Layout;
+
+/// Conduits and buildings taken away since last frame.
+#[derive(SystemParam)]
+struct Removals<'w, 's> {
+    machines: RemovedComponents<'w, 's, Machine>,
+    pipes: RemovedComponents<'w, 's, pipes::Pipe>,
+    lines: RemovedComponents<'w, 's, power::PowerLine>,
+    wires: RemovedComponents<'w, 's, signals::SignalWire>,
+    walls: RemovedComponents<'w, 's, drones::Wall>,
+}
+
+impl Removals<'_, '_> {
+    fn any(&mut self) -> bool {
+        // read every buffer so none of them is left to be seen twice
+        let removed = self.machines.read().count()
+            + self.pipes.read().count()
+            + self.lines.read().count()
+            + self.wires.read().count()
+            + self.walls.read().count();
+        removed > 0
+    }
+}
+
+/// Removals only stay readable for a couple of frames, so they are watched here every
+/// frame rather than from the field steps, which may be paused or slowed.
+#[allow(clippy::type_complexity)]
+fn track_layout(
+    mut layout: ResMut<Layout>,
+    added: Query<
+        (),
+        Or<(
+            Added<Machine>,
+            Added<pipes::Pipe>,
+            Added<power::PowerLine>,
+            Added<signals::SignalWire>,
+            Added<drones::Wall>,
+        )>,
+    >,
+    mut removals: Removals,
+) {
+    if removals.any() || !added.is_empty() {
+        layout.set_changed();
+    }
+}
+
+impl Plugin for FieldTestPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ClearColor(Color::srgb(0.03, 0.03, 0.05)))
+            .init_resource::<Layout>()
+            .add_message::<overload::Instability>()
+            .add_message::<economy::InsufficientFunds>()
+            .add_message::<scenario::ObjectiveCompleted>()
+            .add_message::<scenario::ScenarioFinished>()
+            .init_asset::<refining::RecipeBook>()
+            .init_asset_loader::<refining::RecipeLoader>()
+            .init_asset::<scenario::Scenario>()
+            .init_asset_loader::<scenario::ScenarioLoader>()
+            .add_systems(Startup, (refining::load_recipes, scenario::load_scenarios))
+            .add_systems(
+                OnEnter(InSession),
+                (reset_field, scenario::queue_first_scenario),
+            )
+            // the farm is spawned with the rest of the session's world once loading is done
+            .add_systems(
+                OnTransition {
+                    exited: GameState::Loading,
+                    entered: GameState::InGame,
+                },
+                (
+                    (
+                        setup,
+                        hud::spawn_hud,
+                        economy::spawn_market_graph,
+                        contracts::spawn_contracts_panel,
+                        tutorial::start_tutorial,
+                    ),
+                    (
+                        setup_tool_readout,
+                        power::setup_readout,
+                        controller::setup_readout,
+                        drones::setup_readout,
+                        economy::setup_readout,
+                        scenario::setup_readout,
+                        speed::setup_readout,
+                    ),
+                    save::load_on_start,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    // script editing and the research browser take the keyboard while open,
+                    // including the Escape that closes them
+                    (controller::edit_script, controller::open_editor)
+                        .chain()
+                        .run_if(not(resource_exists::<research::ResearchBrowser>))
+                        .before(toggle_pause),
+                    (research::toggle_browser, research::browse_research)
+                        .chain()
+                        .run_if(not(resource_exists::<controller::ScriptEditor>))
+                        .before(toggle_pause),
+                    // input
+                    (
+                        cursor_input,
+                        tool_input,
+                        influence::placement_input,
+                        battery::toggle_discharge,
+                        machines::upgrade_machine,
+                        wear::repair_machine,
+                        refining::select_recipe,
+                        economy::sell_at_cursor,
+                        economy::toggle_market_graph,
+                        contracts::accept_contract,
+                        scenario::next_scenario,
+                        tutorial::toggle_tutorial,
+                        speed::speed_input,
+                        waveform::edit_waveform,
+                        signals::configure_signals,
+                        logistics::configure_conveyor,
+                        controller::link_machines,
+                        save::save_game,
+                        save::load_game,
+                    )
+                        .chain()
+                        .run_if(keyboard_free),
+                    // building
+                    (
+                        scenario::start_scenario,
+                        place_machine,
+                        pipes::place_pipe,
+                        pipes::remove_pipe,
+                        power::place_line,
+                        power::remove_line,
+                        signals::place_wire,
+                        signals::remove_wire,
+                        logistics::place_conveyor,
+                        logistics::remove_conveyor,
+                        drones::place_wall,
+                        drones::remove_wall,
+                    )
+                        .chain()
+                        .run_if(keyboard_free),
+                    track_layout,
+                    // the field itself, once per frame; after a load it catches up first
+                    offline::catch_up,
+                    speed::run_field_step.run_if(not(resource_exists::<offline::CatchUp>)),
+                    // visualization
+                    (
+                        update_cell_visuals,
+                        battery::update_fill_gauges,
+                        waveform::pulse_emitter_visuals,
+                        wear::update_wear_visuals,
+                        refining::update_progress_bars,
+                        logistics::update_belt_items,
+                        drones::update_drone_visuals,
+                        signals::update_wire_visuals,
+                        controller::draw_links,
+                        update_cursor_visual,
+                        influence::draw_placement_preview,
+                        synergy::draw_synergy_preview,
+                    )
+                        .chain(),
+                    // hud
+                    (
+                        machines::update_tooltips,
+                        power::update_readout,
+                        controller::update_readout,
+                        drones::update_readout,
+                        economy::update_readout,
+                        economy::update_market_graph,
+                        contracts::update_contracts_panel,
+                        research::update_browser_text,
+                        scenario::update_readout,
+                        scenario::log_objectives,
+                        scenario::show_results,
+                        offline::dismiss_summary,
+                        tutorial::run_tutorial,
+                        controller::update_editor_text,
+                        speed::update_readout,
+                        update_tool_readout,
+                    )
+                        .chain(),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(
+                FieldStep,
+                (
+                    // networks
+                    (
+                        pipes::rebuild_networks,
+                        power::rebuild_grids,
+                        signals::rebuild_signal_networks,
+                        synergy::rebuild_synergies,
+                        power::solve_power,
+                    )
+                        .chain(),
+                    // simulation
+                    (
+                        waveform::sample_waveforms,
+                        update_drive,
+                        controller::run_controllers,
+                        apply_machines_to_field,
+                        battery::discharge_into_neighbors,
+                        pipes::solve_networks,
+                        battery::stabilizers_feed_field,
+                        diffuse_and_decay_field,
+                        stabilizers_make_crystal,
+                        refining::run_refiners,
+                        wear::accumulate_wear,
+                        wear::maintain_machines,
+                        signals::evaluate_signals,
+                        overload::build_pressure,
+                        overload::trigger_instability,
+                        overload::recover_disabled_machines,
+                        overload::log_instability,
+                    )
+                        .chain(),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                FixedUpdate,
+                speed::run_fixed_step
+                    .run_if(in_state(GameState::InGame))
+                    .run_if(not(resource_exists::<offline::CatchUp>)),
+            )
+            .add_systems(
+                Update,
+                (refining::report_loading, scenario::report_loading).in_set(LoadingChecks),
+            )
+            .add_systems(
+                FieldFixedStep,
+                (
+                    economy::update_market,
+                    contracts::run_contracts,
+                    research::run_research,
+                    scenario::evaluate_objectives,
+                    drones::rebuild_nav,
+                    drones::plan_jobs,
+                    drones::launch_drones,
+                    drones::run_drones,
+                    logistics::run_inserters,
+                    logistics::move_conveyors,
+                )
+                    .chain(),
+            );
+    }
+}
+
+// -----------------------------
+// Setup
+// -----------------------------
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    settings: Res<Settings>,
+) {
+    // Camera (0.17 idiomatic: spawn the component, required components are inserted automatically)
+    // commands.spawn((
+    //     Camera3d::default(),
+    //     Transform::from_xyz(0.0, 15.0, 15.0).looking_at(Vec3::ZERO, Vec3::Y),
+    // ));
+
+    // the session's camera is spawned by the app alongside this
+
+    // Light
+    commands.spawn((
+        DirectionalLight {
+            illuminance: 30_000.0,
+            shadows_enabled: settings.shadows,
+            ..default()
+        },
+        Transform::from_rotation(Quat::from_euler(EulerRot::XYZ, -1.1, 0.6, 0.0)),
+        DespawnOnExit(InSession),
+    ));
+
+    // Shared cell mesh
+    let cell_mesh = meshes.add(Cuboid::new(1.0, 0.2, 1.0));
+
+    // Grid cells
+    for y in 0..H {
+        for x in 0..W {
+            let mat = materials.add(StandardMaterial {
+                base_color: Color::srgb(0.08, 0.10, 0.14),
+                perceptual_roughness: 0.9,
+                metallic: 0.0,
+                ..default()
+            });
+
+            commands.spawn((
+                Mesh3d(cell_mesh.clone()),
+                MeshMaterial3d(mat.clone()),
+                Transform::from_xyz(
+                    (x - W / 2) as f32 * CELL_SPACING,
+                    0.0,
+                    (y - H / 2) as f32 * CELL_SPACING,
+                ),
+                Cell { x, y },
+                CellMat(mat),
+                DespawnOnExit(InSession),
+            ));
+        }
+    }
+
+    // Cursor visualization
+    let cursor_mesh = meshes.add(Cuboid::new(1.02, 0.6, 1.02));
+    let cursor_mat = materials.add(StandardMaterial {
+        base_color: Color::srgb(0.9, 0.9, 0.2),
+        emissive: Color::srgb(0.3, 0.3, 0.05).into(),
+        ..default()
+    });
+
+    commands.spawn((
+        Mesh3d(cursor_mesh),
+        MeshMaterial3d(cursor_mat),
+        Transform::from_xyz(0.0, 0.55, 0.0),
+        CursorViz,
+        DespawnOnExit(InSession),
+    ));
+}
+
+/// Puts the farm back to a fresh field at the start of every session, so nothing from
+/// the last game carries over. A loaded save then replaces what it stores.
+fn reset_field(mut commands: Commands) {
+    commands.insert_resource(FieldGrid::new(W, H));
+    commands.insert_resource(CursorCell { x: W / 2, y: H / 2 });
+    commands.insert_resource(SelectedTool(Tool::Emitter));
+    commands.insert_resource(pipes::PipeNetworks::default());
+    commands.insert_resource(influence::Placement::default());
+    commands.insert_resource(signals::SignalNetworks::default());
+    commands.insert_resource(controller::Linking::default());
+    commands.insert_resource(synergy::Neighborhood::default());
+    commands.insert_resource(drones::JobQueue::default());
+    commands.insert_resource(drones::NavGrid::default());
+    commands.insert_resource(economy::Funds::default());
+    commands.insert_resource(economy::Market::default());
+    commands.insert_resource(contracts::Contracts::default());
+    commands.insert_resource(research::Research::default());
+    commands.insert_resource(speed::SimSpeed::default());
+    commands.insert_resource(speed::SimClock::default());
+    commands.insert_resource(power::PowerGrids::default());
+    commands.insert_resource(power::PowerBalance::default());
+    commands.remove_resource::<controller::ScriptEditor>();
+    commands.remove_resource::<research::ResearchBrowser>();
+    commands.remove_resource::<scenario::ScenarioRun>();
+    commands.remove_resource::<offline::CatchUp>();
+}
+
+fn setup_tool_readout(mut commands: Commands, panel: Res<hud::HudPanel>) {
+    hud::spawn_readout(&mut commands, &panel, ToolReadout);
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+fn cursor_input(keys: Res<ButtonInput<KeyCode>>, mut cursor: ResMut<CursorCell>) {
+    let mut dx = 0;
+    let mut dy = 0;
+
+    if keys.just_pressed(KeyCode::ArrowLeft) {
+        dx -= 1;
+    }
+    if keys.just_pressed(KeyCode::ArrowRight) {
+        dx += 1;
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        dy -= 1;
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        dy += 1;
+    }
+
+    cursor.x = (cursor.x + dx).clamp(0, W - 1);
+    cursor.y = (cursor.y + dy).clamp(0, H - 1);
+}
+
+/// Gameplay keys are ignored while the script editor or the research browser is open.
+fn keyboard_free(
+    editor: Option<Res<controller::ScriptEditor>>,
+    browser: Option<Res<research::ResearchBrowser>>,
+) -> bool {
+    editor.is_none() && browser.is_none()
+}
+
+/// Number keys pick the first ten tools and Tab cycles through the rest; tools that
+/// still need research are skipped. Picking another tool resets the placement shape
+/// to that tool's own.
+fn tool_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    research: Res<research::Research>,
+    mut tool: ResMut<SelectedTool>,
+    mut placement: ResMut<influence::Placement>,
+) {
+    let held = tool.0;
+    let digits = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+        KeyCode::Digit0,
+    ];
+    for (key, &picked) in digits.iter().zip(&Tool::ALL) {
+        if !keys.just_pressed(*key) {
+            continue;
+        }
+        if research.has_tool(picked) {
+            tool.0 = picked;
+        } else {
+            info!("{picked:?} needs research (T to browse)");
+        }
+    }
+    if keys.just_pressed(KeyCode::Tab) {
+        let i = Tool::ALL.iter().position(|&t| t == tool.0).unwrap_or(0);
+        if let Some(&next) = (1..=Tool::ALL.len())
+            .map(|step| &Tool::ALL[(i + step) % Tool::ALL.len()])
+            .find(|&&t| research.has_tool(t))
+        {
+            tool.0 = next;
+        }
+    }
+    // a loaded save may have less research than the tool in hand needs
+    if !research.has_tool(tool.0) {
+        tool.0 = Tool::Emitter;
+    }
+    if tool.0 != held {
+        placement.0.shape = influence::Shape::default_for(tool.0);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn place_machine(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    tool: Res<SelectedTool>,
+    placement: Res<influence::Placement>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    occupancy: Occupancy,
+    mut funds: ResMut<economy::Funds>,
+    mut refused: MessageWriter<economy::InsufficientFunds>,
+) {
+    if !keys.just_pressed(KeyCode::Space) {
+        return;
+    }
+    // conduits have their own placement
+    let Some(def) = machines::machine_def(tool.0) else {
+        return;
+    };
+
+    // prevent stacking multiple machines (or conduits) on the same cell
+    if !occupancy.is_free(cursor.x, cursor.y) {
+        return;
+    }
+    if funds.0 < def.price {
+        refused.write(economy::InsufficientFunds {
+            name: def.name,
+            price: def.price,
+        });
+        return;
+    }
+    funds.0 -= def.price;
+
+    let e = machines::spawn_machine(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        tool.0,
+        0,
+        cursor.x,
+        cursor.y,
+    );
+    commands.entity(e).insert(placement.0);
+}
+
+fn update_cursor_visual(cursor: Res<CursorCell>, mut q: Query<&mut Transform, With<CursorViz>>) {
+    if !cursor.is_changed() {
+        return;
+    }
+    let Ok(mut t) = q.single_mut() else { return };
+    t.translation = cell_world(cursor.x, cursor.y) + Vec3::Y * 0.55;
+}
+
+fn cell_world(x: i32, y: i32) -> Vec3 {
+    Vec3::new(
+        (x - W / 2) as f32 * CELL_SPACING,
+        0.0,
+        (y - H / 2) as f32 * CELL_SPACING,
+    )
+}
+
+fn world_cell(p: Vec3) -> (i32, i32) {
+    (
+        ((p.x / CELL_SPACING).round() as i32) + W / 2,
+        ((p.z / CELL_SPACING).round() as i32) + H / 2,
+    )
+}
+
+// -----------------------------
+// Simulation
+// -----------------------------
+
+/// One tick of the field: networks, machines and the aether/crystal grid. It renders
+/// nothing, so it can be run many times in a frame to fast-forward.
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+struct FieldStep;
+
+/// Drones, logistics and the economy, run from `FixedUpdate`.
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+struct FieldFixedStep;
+
+fn update_drive(
+    mut q: Query<(
+        &mut Drive,
+        &power::Powered,
+        &signals::Receiver,
+        &wear::Wear,
+        Option<&waveform::Waveform>,
+    )>,
+) {
+    for (mut drive, power, receiver, wear, wave) in &mut q {
+        drive.0 =
+            power.factor() * receiver.factor() * wear.factor() * wave.map_or(1.0, |w| w.level());
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn apply_machines_to_field(
+    time: Res<Time>,
+    mut grid: ResMut<FieldGrid>,
+    mut machines: Query<
+        (
+            &Transform,
+            &Machine,
+            &Drive,
+            &influence::Influence,
+            &synergy::Synergies,
+            Option<&mut battery::AetherStore>,
+        ),
+        Without<overload::Disabled>,
+    >,
+) {
+    let dt = time.delta_secs();
+    for (t, m, drive, inf, syn, mut store) in &mut machines {
+        let (gx, gy) = world_cell(t.translation);
+        let strength = syn.strength(m) * drive.0;
+
+        for (xx, yy, w) in inf.cells(gx, gy, syn.radius(m)) {
+            if !grid.in_bounds(xx, yy) {
+                continue;
+            }
+
+            let idx = grid.idx(xx, yy);
+            match (m.kind, store.as_deref_mut()) {
+                // sinks bank what they drain and stop once full or while discharging
+                (Tool::Sink, Some(store)) => {
+                    if store.discharging || store.is_full() {
+                        continue;
+                    }
+                    let drained = (-strength * w * dt).min(grid.aether[idx]).min(store.room());
+                    grid.aether[idx] -= drained;
+                    store.stored += drained;
+                }
+                (Tool::Emitter | Tool::Sink, _) => {
+                    grid.aether[idx] =
+                        (grid.aether[idx] + strength * w * dt).clamp(0.0, MAX_AETHER);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn diffuse_and_decay_field(time: Res<Time>, mut grid: ResMut<FieldGrid>) {
+    let dt = time.delta_secs();
+    let (w, h) = (grid.w, grid.h);
+    let mut next = grid.aether.clone();
+
+    // 4-neighbor diffusion
+    for y in 0..h {
+        for x in 0..w {
+            let i = grid.idx(x, y);
+            let c = grid.aether[i];
+
+            let mut sum = 0.0;
+            let mut count = 0.0;
+            for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                if grid.in_bounds(nx, ny) {
+                    sum += grid.aether[grid.idx(nx, ny)];
+                    count += 1.0;
+                }
+            }
+
+            let neighbor_avg = if count > 0.0 { sum / count } else { c };
+            let diff = (neighbor_avg - c) * DIFFUSION * dt;
+            let decayed = (c + diff) * (1.0 - DECAY * dt);
+
+            next[i] = decayed.clamp(0.0, MAX_AETHER);
+        }
+    }
+
+    grid.aether = next;
+}
+
+fn stabilizers_make_crystal(
+    time: Res<Time>,
+    mut grid: ResMut<FieldGrid>,
+    machines: Query<
+        (
+            &Transform,
+            &Machine,
+            &Drive,
+            &influence::Influence,
+            &synergy::Synergies,
+        ),
+        Without<overload::Disabled>,
+    >,
+) {
+    let dt = time.delta_secs();
+
+    for (t, m, drive, inf, syn) in &machines {
+        if m.kind != Tool::Stabilizer {
+            continue;
+        }
+
+        let (gx, gy) = world_cell(t.translation);
+        let sweet_spot = (SWEET_SPOT.0 - syn.sweet_spot)..=(SWEET_SPOT.1 + syn.sweet_spot);
+        let middle = (sweet_spot.start() + sweet_spot.end()) * 0.5;
+        let half_width = (sweet_spot.end() - sweet_spot.start()) * 0.5;
+
+        for (xx, yy, w) in inf.cells(gx, gy, syn.radius(m)) {
+            if !grid.in_bounds(xx, yy) {
+                continue;
+            }
+            let idx = grid.idx(xx, yy);
+            let a = grid.aether[idx];
+
+            // “sweet spot” stabilizer: converts Aether -> Crystal
+            if sweet_spot.contains(&a) {
+                let convert = (syn.strength(m) * drive.0 * w * dt).min(a);
+                grid.aether[idx] -= convert;
+                // new crystal is purer the closer to the middle of the sweet spot it grew
+                let purity = 1.0 - (a - middle).abs() / half_width;
+                let total = grid.crystal[idx] + convert;
+                if total > 0.0 {
+                    grid.purity[idx] =
+                        (grid.purity[idx] * grid.crystal[idx] + purity * convert) / total;
+                }
+                grid.crystal[idx] = total;
+            }
+        }
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+fn update_cell_visuals(
+    grid: Res<FieldGrid>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    cells: Query<(&Cell, &CellMat)>,
+) {
+    if !grid.is_changed() {
+        return;
+    }
+
+    for (c, mat_h) in &cells {
+        let i = grid.idx(c.x, c.y);
+        let a = grid.aether[i];
+        let cr = grid.crystal[i];
+
+        let a_t = (a / MAX_AETHER).clamp(0.0, 1.0);
+        let cr_t = (cr / 12.0).clamp(0.0, 1.0);
+        let warn_t = overload::warning_level(grid.pressure[i]);
+
+        let base = Vec3::new(0.07, 0.09, 0.13);
+        let aether_col = Vec3::new(0.10, 0.85, 0.95) * a_t;
+        let crystal_col = Vec3::new(0.85, 0.85, 1.00) * cr_t;
+        let warn_col = Vec3::new(1.00, 0.20, 0.10) * warn_t;
+
+        let rgb = (base.lerp(warn_col, warn_t) + aether_col * (1.0 - warn_t) + crystal_col)
+            .clamp(Vec3::ZERO, Vec3::splat(1.0));
+
+        if let Some(mat) = materials.get_mut(&mat_h.0) {
+            mat.base_color = Color::srgb(rgb.x, rgb.y, rgb.z);
+            let e = aether_col * 0.25 + warn_col * 0.6;
+            mat.emissive = Color::srgb(e.x, e.y, e.z).into();
+        }
+    }
+}
+
+fn update_tool_readout(tool: Res<SelectedTool>, mut q: Query<&mut Text, With<ToolReadout>>) {
+    if !tool.is_changed() {
+        return;
+    }
+    let Ok(mut text) = q.single_mut() else { return };
+    let name = machines::machine_def(tool.0).map_or_else(
+        || format!("{:?}", tool.0),
+        |d| format!("{}, {:.0} credits", d.name, d.price),
+    );
+    text.0 = format!("Tool: {name} (Tab to cycle)");
+}
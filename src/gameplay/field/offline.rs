@@ -0,0 +1,207 @@
+﻿use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+
+use super::FieldGrid;
+use super::speed;
+use crate::state::InSession;
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+// Longest absence that is simulated; anything past it is lost.
+const MAX_AWAY_SECS: f32 = 2.0 * 60.0 * 60.0;
+// Absences shorter than this aren't worth a summary.
+const MIN_AWAY_SECS: f32 = 60.0;
+// Size of one catch-up tick. Diffusion goes unstable above 1 / DIFFUSION.
+const CATCH_UP_STEP: f32 = 0.15;
+// Catch-up ticks run per frame, so a long absence plays out over a few seconds behind
+// the progress overlay instead of freezing the game.
+const CATCH_UP_TICKS_PER_FRAME: u32 = 200;
+const SUMMARY_SECS: f32 = 12.0;
+
+// -----------------------------
+// Resources + Components
+// -----------------------------
+
+/// Time the player was away, being simulated a slice per frame once a loaded farm exists.
+#[derive(Resource)]
+pub(super) struct CatchUp {
+    away: f32,
+    /// Ticks still to run, out of `total`.
+    left: u32,
+    total: u32,
+    /// Crystal on the field when catching up started.
+    crystal_before: Option<f32>,
+}
+
+/// Covers the field while it catches up.
+#[derive(Component)]
+pub(super) struct CatchUpOverlay;
+
+#[derive(Component)]
+pub(super) struct CatchUpText;
+
+#[derive(Component)]
+pub(super) struct AwaySummary(Timer);
+
+/// Wall-clock time in whole seconds, as stored in saves.
+pub(super) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Queues the time since `saved_at` to be simulated. Old saves have no timestamp.
+pub(super) fn queue_catch_up(commands: &mut Commands, saved_at: u64) {
+    if saved_at == 0 {
+        return;
+    }
+    let away = now_secs().saturating_sub(saved_at) as f32;
+    if away < MIN_AWAY_SECS {
+        return;
+    }
+    let ticks = (away.min(MAX_AWAY_SECS) / CATCH_UP_STEP) as u32;
+    commands.insert_resource(CatchUp {
+        away,
+        left: ticks,
+        total: ticks,
+        crystal_before: None,
+    });
+    commands.spawn((
+        panel("Catching up", (body_text(""), CatchUpText)),
+        CatchUpOverlay,
+        DespawnOnExit(InSession),
+    ));
+}
+
+fn clock(secs: f32) -> String {
+    let mins = (secs / 60.0) as u32;
+    format!("{}h {:02}m", mins / 60, mins % 60)
+}
+
+/// Full-screen overlay with a centred box holding a title and `body`.
+fn panel(title: &str, body: impl Bundle) -> impl Bundle {
+    (
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        children![(
+            Node {
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(10.0),
+                padding: UiRect::all(Val::Px(24.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.02, 0.02, 0.06, 0.92)),
+            children![
+                (
+                    Text::new(title),
+                    TextFont {
+                        font_size: 28.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.6, 0.85, 1.0)),
+                ),
+                body,
+            ],
+        )],
+    )
+}
+
+fn body_text(text: &str) -> impl Bundle {
+    (
+        Text::new(text),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+    )
+}
+
+// -----------------------------
+// Simulation
+// -----------------------------
+
+/// Fast-forwards the field and everything on the fixed step through the time spent
+/// away, a slice of coarse ticks per frame, then shows what grew meanwhile.
+pub(super) fn catch_up(world: &mut World) {
+    let Some(mut catch_up) = world.remove_resource::<CatchUp>() else {
+        return;
+    };
+    let crystal = |world: &World| world.resource::<FieldGrid>().crystal.iter().sum::<f32>();
+    let crystal_before = *catch_up
+        .crystal_before
+        .get_or_insert_with(|| crystal(world));
+
+    let ticks = catch_up.left.min(CATCH_UP_TICKS_PER_FRAME);
+    speed::fast_forward(world, ticks, Duration::from_secs_f32(CATCH_UP_STEP));
+    catch_up.left -= ticks;
+
+    if catch_up.left > 0 {
+        let done = 1.0 - catch_up.left as f32 / catch_up.total as f32;
+        let line = format!("{:.0}% of {}", done * 100.0, clock(catch_up.away));
+        let mut texts = world.query_filtered::<&mut Text, With<CatchUpText>>();
+        for mut text in texts.iter_mut(world) {
+            text.0.clone_from(&line);
+        }
+        world.insert_resource(catch_up);
+        return;
+    }
+
+    let overlays: Vec<Entity> = world
+        .query_filtered::<Entity, With<CatchUpOverlay>>()
+        .iter(world)
+        .collect();
+    for e in overlays {
+        world.despawn(e);
+    }
+
+    let CatchUp { away, total, .. } = catch_up;
+    let grown = crystal(world) - crystal_before;
+    info!(
+        "simulated {} away in {total} steps: {grown:+.1} crystal",
+        clock(away.min(MAX_AWAY_SECS))
+    );
+
+    let mut lines = vec![format!("Away for {}", clock(away))];
+    if away > MAX_AWAY_SECS {
+        lines.push(format!("(only the first {} counted)", clock(MAX_AWAY_SECS)));
+    }
+    lines.push(format!("Crystal: {grown:+.1}"));
+
+    world.spawn((
+        panel("While you were away", body_text(&lines.join("\n"))),
+        Pickable::IGNORE,
+        AwaySummary(Timer::from_seconds(SUMMARY_SECS, TimerMode::Once)),
+        DespawnOnExit(InSession),
+    ));
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+/// The summary goes away after a while or on any key.
+pub(super) fn dismiss_summary(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut commands: Commands,
+    mut q: Query<(Entity, &mut AwaySummary)>,
+) {
+    for (e, mut summary) in &mut q {
+        // the key that loaded the save is still down the frame the summary appears
+        let shown = summary.0.elapsed_secs() > 0.0;
+        let key = keys.get_just_pressed().next().is_some();
+        if summary.0.tick(time.delta()).is_finished() || (shown && key) {
+            commands.entity(e).despawn();
+        }
+    }
+}
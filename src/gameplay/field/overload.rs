@@ -0,0 +1,160 @@
+﻿use bevy::prelude::*;
+
+use super::{FieldGrid, MAX_AETHER, Machine, world_cell};
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+// Aether above this level counts as saturated and builds pressure.
+const OVERLOAD_THRESHOLD: f32 = 0.9 * MAX_AETHER;
+const PRESSURE_RATE: f32 = 0.25;
+const PRESSURE_RELIEF: f32 = 0.5;
+const PRESSURE_LIMIT: f32 = 1.0;
+
+// Cells start glowing once pressure passes this fraction of the limit.
+const WARNING_START: f32 = 0.35;
+
+const BURST_RADIUS: i32 = 2;
+const BURST_SHATTER: f32 = 0.75;
+const BURST_DAMAGE: f32 = 40.0;
+const DISABLE_SECS: f32 = 6.0;
+
+// -----------------------------
+// Components + Messages
+// -----------------------------
+
+#[derive(Component, Clone, Copy)]
+pub(super) struct Integrity(pub(super) f32);
+
+impl Default for Integrity {
+    fn default() -> Self {
+        Self(100.0)
+    }
+}
+
+/// Machine knocked offline by an instability burst. Removed once the timer runs out.
+#[derive(Component)]
+pub(super) struct Disabled(Timer);
+
+#[derive(Message, Debug, Clone, Copy)]
+pub(super) struct Instability {
+    pub x: i32,
+    pub y: i32,
+    pub shattered: f32,
+    pub machines_hit: u32,
+}
+
+// -----------------------------
+// Simulation
+// -----------------------------
+
+pub(super) fn build_pressure(time: Res<Time>, mut grid: ResMut<FieldGrid>) {
+    let dt = time.delta_secs();
+
+    for i in 0..grid.pressure.len() {
+        let excess = grid.aether[i] - OVERLOAD_THRESHOLD;
+        let p = if excess > 0.0 {
+            grid.pressure[i] + (1.0 + excess) * PRESSURE_RATE * dt
+        } else {
+            grid.pressure[i] - PRESSURE_RELIEF * dt
+        };
+        grid.pressure[i] = p.max(0.0);
+    }
+}
+
+pub(super) fn trigger_instability(
+    mut commands: Commands,
+    mut grid: ResMut<FieldGrid>,
+    mut machines: Query<(Entity, &Transform, &mut Integrity), With<Machine>>,
+    mut out: MessageWriter<Instability>,
+) {
+    for y in 0..grid.h {
+        for x in 0..grid.w {
+            let i = grid.idx(x, y);
+            if grid.pressure[i] < PRESSURE_LIMIT {
+                continue;
+            }
+
+            // the burst vents the cell: pressure and aether are released together
+            grid.pressure[i] = 0.0;
+            grid.aether[i] = 0.0;
+
+            let mut shattered = 0.0;
+            for yy in (y - BURST_RADIUS)..=(y + BURST_RADIUS) {
+                for xx in (x - BURST_RADIUS)..=(x + BURST_RADIUS) {
+                    if !grid.in_bounds(xx, yy) {
+                        continue;
+                    }
+                    let dx = xx - x;
+                    let dy = yy - y;
+                    if dx * dx + dy * dy > BURST_RADIUS * BURST_RADIUS {
+                        continue;
+                    }
+                    let j = grid.idx(xx, yy);
+                    let lost = grid.crystal[j] * BURST_SHATTER;
+                    grid.crystal[j] -= lost;
+                    shattered += lost;
+                }
+            }
+
+            let mut machines_hit = 0;
+            for (e, t, mut integrity) in &mut machines {
+                let (gx, gy) = world_cell(t.translation);
+                let dx = gx - x;
+                let dy = gy - y;
+                if dx * dx + dy * dy > BURST_RADIUS * BURST_RADIUS {
+                    continue;
+                }
+
+                machines_hit += 1;
+                integrity.0 -= BURST_DAMAGE;
+                if integrity.0 <= 0.0 {
+                    commands.entity(e).despawn();
+                } else {
+                    commands
+                        .entity(e)
+                        .insert(Disabled(Timer::from_seconds(DISABLE_SECS, TimerMode::Once)));
+                }
+            }
+
+            out.write(Instability {
+                x,
+                y,
+                shattered,
+                machines_hit,
+            });
+        }
+    }
+}
+
+pub(super) fn recover_disabled_machines(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut q: Query<(Entity, &mut Disabled)>,
+) {
+    for (e, mut disabled) in &mut q {
+        disabled.0.tick(time.delta());
+        if disabled.0.is_finished() {
+            commands.entity(e).remove::<Disabled>();
+        }
+    }
+}
+
+pub(super) fn log_instability(mut ev: MessageReader<Instability>) {
+    for burst in ev.read() {
+        warn!(
+            "aether instability at ({}, {}): shattered {:.1} crystal, hit {} machine(s)",
+            burst.x, burst.y, burst.shattered, burst.machines_hit
+        );
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+/// 0 while a cell is calm, ramping to 1 as it approaches a burst.
+pub(super) fn warning_level(pressure: f32) -> f32 {
+    ((pressure / PRESSURE_LIMIT - WARNING_START) / (1.0 - WARNING_START)).clamp(0.0, 1.0)
+}
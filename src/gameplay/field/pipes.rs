@@ -0,0 +1,257 @@
+﻿use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+
+use super::battery::AetherStore;
+use super::overload::Disabled;
+use super::synergy::Synergies;
+use super::{
+    CursorCell, Drive, Layout, Machine, Occupancy, SelectedTool, Tool, cell_world, world_cell,
+};
+use crate::state::InSession;
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+// Aether per second a network can move, regardless of how many producers feed it.
+const PIPE_THROUGHPUT: f32 = 5.0;
+// Extra headroom each pipe segment adds, so longer runs are not pure bottlenecks.
+const THROUGHPUT_PER_SEGMENT: f32 = 0.5;
+
+// -----------------------------
+// Resources + Components
+// -----------------------------
+
+#[derive(Component)]
+pub(super) struct Pipe {
+    x: i32,
+    y: i32,
+}
+
+impl Pipe {
+    pub(super) fn cell(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+}
+
+pub(super) struct Network {
+    pub(super) pipes: usize,
+    pub(super) members: Vec<Entity>,
+}
+
+impl Network {
+    fn throughput(&self) -> f32 {
+        PIPE_THROUGHPUT + self.pipes as f32 * THROUGHPUT_PER_SEGMENT
+    }
+}
+
+#[derive(Resource, Default)]
+pub(super) struct PipeNetworks {
+    pub(super) networks: Vec<Network>,
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+pub(super) fn place_pipe(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    tool: Res<SelectedTool>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    occupancy: Occupancy,
+) {
+    if tool.0 != Tool::Pipe || !keys.just_pressed(KeyCode::Space) {
+        return;
+    }
+    if !occupancy.is_free(cursor.x, cursor.y) {
+        return;
+    }
+
+    spawn_pipe(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        cursor.x,
+        cursor.y,
+    );
+}
+
+pub(super) fn spawn_pipe(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    x: i32,
+    y: i32,
+) -> Entity {
+    commands
+        .spawn((
+            Mesh3d(meshes.add(Cuboid::new(0.35, 0.15, 0.35))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::srgb(0.55, 0.55, 0.6),
+                metallic: 0.8,
+                perceptual_roughness: 0.3,
+                ..default()
+            })),
+            Transform::from_translation(cell_world(x, y) + Vec3::Y * 0.18),
+            Pipe { x, y },
+            DespawnOnExit(InSession),
+        ))
+        .id()
+}
+
+pub(super) fn remove_pipe(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut commands: Commands,
+    pipes: Query<(Entity, &Pipe)>,
+) {
+    if !keys.just_pressed(KeyCode::KeyX) {
+        return;
+    }
+
+    for (e, p) in &pipes {
+        if p.cell() == (cursor.x, cursor.y) {
+            commands.entity(e).despawn();
+        }
+    }
+}
+
+// -----------------------------
+// Network solving
+// -----------------------------
+
+/// Rebuilds the connected pipe networks whenever pipes or machines come and go.
+pub(super) fn rebuild_networks(
+    layout: Res<Layout>,
+    mut networks: ResMut<PipeNetworks>,
+    pipes: Query<&Pipe>,
+    machines: Query<(Entity, &Transform), With<Machine>>,
+) {
+    if !layout.is_changed() {
+        return;
+    }
+
+    let cells = pipes.iter().map(Pipe::cell);
+    let members = machines.iter().map(|(e, t)| (e, world_cell(t.translation)));
+    networks.networks = connect_networks(cells, members)
+        .into_iter()
+        .map(|(pipes, members)| Network { pipes, members })
+        .collect();
+}
+
+/// Flood fills conduit cells into connected components.
+/// Returns the component id of every cell and the number of cells in each component.
+pub(super) fn label_cells(
+    cells: impl Iterator<Item = (i32, i32)>,
+) -> (HashMap<(i32, i32), usize>, Vec<usize>) {
+    let mut component: HashMap<(i32, i32), usize> = cells.map(|c| (c, usize::MAX)).collect();
+    let starts: Vec<(i32, i32)> = component.keys().copied().collect();
+    let mut sizes = Vec::new();
+
+    for start in starts {
+        if component[&start] != usize::MAX {
+            continue;
+        }
+        let id = sizes.len();
+        let mut size = 0;
+        let mut queue = VecDeque::from([start]);
+        component.insert(start, id);
+
+        while let Some((x, y)) = queue.pop_front() {
+            size += 1;
+            for n in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                if component.get(&n) == Some(&usize::MAX) {
+                    component.insert(n, id);
+                    queue.push_back(n);
+                }
+            }
+        }
+        sizes.push(size);
+    }
+
+    (component, sizes)
+}
+
+/// Labels conduit cells into networks and attaches every machine that sits next to one.
+/// Returns the segment count and members of each network.
+pub(super) fn connect_networks(
+    cells: impl Iterator<Item = (i32, i32)>,
+    machines: impl Iterator<Item = (Entity, (i32, i32))>,
+) -> Vec<(usize, Vec<Entity>)> {
+    let (component, sizes) = label_cells(cells);
+    let mut networks: Vec<(usize, Vec<Entity>)> =
+        sizes.into_iter().map(|size| (size, Vec::new())).collect();
+
+    // a machine joins only the first network touching its cell, checked in a fixed
+    // order, so one sitting between two can't feed (or draw from) both at once
+    for (e, (x, y)) in machines {
+        if let Some(&id) = [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+            .iter()
+            .find_map(|n| component.get(n))
+        {
+            networks[id].1.push(e);
+        }
+    }
+
+    networks
+}
+
+/// Moves aether from producers to consumers inside each network, limited by throughput.
+///
+/// Producers are emitters (which generate what they offer) and sinks that are full or
+/// discharging. Consumers are any other member with room in its store. Supply is drawn
+/// and demand is served proportionally, so no single machine starves the rest.
+pub(super) fn solve_networks(
+    time: Res<Time>,
+    networks: Res<PipeNetworks>,
+    mut machines: Query<
+        (&Machine, &Drive, &Synergies, Option<&mut AetherStore>),
+        Without<Disabled>,
+    >,
+) {
+    let dt = time.delta_secs();
+
+    for net in &networks.networks {
+        let mut supply = 0.0;
+        let mut demand = 0.0;
+
+        for &e in &net.members {
+            let Ok((m, drive, syn, store)) = machines.get(e) else {
+                continue;
+            };
+            match (m.kind, store) {
+                (Tool::Emitter, _) => supply += syn.strength(m) * drive.0 * dt,
+                (Tool::Sink, Some(s)) if s.discharging || s.is_full() => supply += s.stored,
+                (_, Some(s)) if !s.discharging && m.kind != Tool::Sink => demand += s.room(),
+                _ => {}
+            }
+        }
+
+        let flow = supply.min(demand).min(net.throughput() * dt);
+        if flow <= 0.0 {
+            continue;
+        }
+        let draw = flow / supply;
+        let serve = flow / demand;
+
+        for &e in &net.members {
+            let Ok((m, _, _, store)) = machines.get_mut(e) else {
+                continue;
+            };
+            let Some(mut s) = store else { continue };
+            match m.kind {
+                Tool::Sink if s.discharging || s.is_full() => s.stored -= s.stored * draw,
+                Tool::Sink => {}
+                _ if !s.discharging => {
+                    let room = s.room();
+                    s.stored += room * serve;
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,337 @@
+﻿use bevy::prelude::*;
+
+use super::hud::{HudPanel, spawn_readout};
+use super::machines::machine_def;
+use super::overload::Disabled;
+use super::pipes::connect_networks;
+use super::synergy::Synergies;
+use super::wear::Wear;
+use super::{CursorCell, Layout, Machine, Occupancy, SelectedTool, Tool, cell_world, world_cell};
+use crate::state::InSession;
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+// Power per unit of |strength| for field machines; the others that use power draw a flat rate.
+// Both are divided by the machine tier's efficiency.
+const DRAW_PER_STRENGTH: f32 = 0.5;
+const STABILIZER_DRAW: f32 = 3.0;
+const CONTROLLER_DRAW: f32 = 1.0;
+const MAINTENANCE_DRAW: f32 = 2.0;
+const REFINER_DRAW: f32 = 4.0;
+const INSERTER_DRAW: f32 = 0.5;
+const DRONE_BAY_DRAW: f32 = 3.0;
+
+// Below this supply fraction a machine shuts down instead of browning out.
+const MIN_RUNNING_SUPPLY: f32 = 0.2;
+
+// -----------------------------
+// Resources + Components
+// -----------------------------
+
+#[derive(Component)]
+pub(super) struct PowerLine {
+    x: i32,
+    y: i32,
+}
+
+impl PowerLine {
+    pub(super) fn cell(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+}
+
+#[derive(Component)]
+pub(super) struct PowerCell {
+    charge: f32,
+    capacity: f32,
+}
+
+/// Fraction of its demand a machine received this tick (0..1).
+/// Machines off the grid stay at 0 and do nothing; so do the ones that draw no power.
+#[derive(Component, Clone, Copy, Default)]
+pub(super) struct Powered(pub(super) f32);
+
+impl Powered {
+    pub(super) fn factor(&self) -> f32 {
+        if self.0 < MIN_RUNNING_SUPPLY {
+            0.0
+        } else {
+            self.0
+        }
+    }
+}
+
+/// Fraction of its generation a generator actually delivered this tick (0..1),
+/// to consumers or into cells. Generators wear with this rather than with `Powered`.
+#[derive(Component, Clone, Copy, Default)]
+pub(super) struct Output(pub(super) f32);
+
+#[derive(Resource, Default)]
+pub(super) struct PowerGrids {
+    grids: Vec<Vec<Entity>>,
+}
+
+/// Totals across every grid, per second, for the HUD.
+#[derive(Resource, Default)]
+pub(super) struct PowerBalance {
+    generation: f32,
+    demand: f32,
+    stored: f32,
+    capacity: f32,
+}
+
+#[derive(Component)]
+pub(super) struct PowerReadout;
+
+impl PowerCell {
+    pub(super) fn charge(&self) -> f32 {
+        self.charge
+    }
+
+    pub(super) fn set_charge(&mut self, charge: f32) {
+        self.charge = charge.clamp(0.0, self.capacity);
+    }
+
+    pub(super) fn set_capacity(&mut self, capacity: f32) {
+        self.capacity = capacity;
+        self.charge = self.charge.min(capacity);
+    }
+}
+
+pub(super) fn power_draw(m: &Machine) -> f32 {
+    let base = match m.kind {
+        Tool::Emitter | Tool::Sink => m.strength.abs() * DRAW_PER_STRENGTH,
+        Tool::Stabilizer => STABILIZER_DRAW,
+        Tool::Controller => CONTROLLER_DRAW,
+        Tool::MaintenanceBay => MAINTENANCE_DRAW,
+        Tool::Refiner => REFINER_DRAW,
+        Tool::Inserter => INSERTER_DRAW,
+        Tool::DroneBay => DRONE_BAY_DRAW,
+        Tool::Generator | Tool::PowerCell | Tool::Sensor | Tool::Logic | Tool::Chest => 0.0,
+        Tool::Pipe | Tool::PowerLine | Tool::SignalWire | Tool::Conveyor | Tool::Wall => 0.0,
+    };
+    let efficiency = machine_def(m.kind).map_or(1.0, |d| d.tier(m.tier).efficiency);
+    base / efficiency
+}
+
+pub(super) fn power_cell(capacity: f32) -> PowerCell {
+    PowerCell {
+        charge: 0.0,
+        capacity,
+    }
+}
+
+// -----------------------------
+// Setup
+// -----------------------------
+
+pub(super) fn setup_readout(mut commands: Commands, panel: Res<HudPanel>) {
+    spawn_readout(&mut commands, &panel, PowerReadout);
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+pub(super) fn place_line(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    tool: Res<SelectedTool>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    occupancy: Occupancy,
+) {
+    if tool.0 != Tool::PowerLine || !keys.just_pressed(KeyCode::Space) {
+        return;
+    }
+    if !occupancy.is_free(cursor.x, cursor.y) {
+        return;
+    }
+
+    spawn_line(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        cursor.x,
+        cursor.y,
+    );
+}
+
+pub(super) fn spawn_line(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    x: i32,
+    y: i32,
+) -> Entity {
+    commands
+        .spawn((
+            Mesh3d(meshes.add(Cuboid::new(0.15, 0.1, 0.15))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::srgb(0.95, 0.6, 0.15),
+                emissive: Color::srgb(0.3, 0.15, 0.0).into(),
+                ..default()
+            })),
+            Transform::from_translation(cell_world(x, y) + Vec3::Y * 0.15),
+            PowerLine { x, y },
+            DespawnOnExit(InSession),
+        ))
+        .id()
+}
+
+pub(super) fn remove_line(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut commands: Commands,
+    lines: Query<(Entity, &PowerLine)>,
+) {
+    if !keys.just_pressed(KeyCode::KeyX) {
+        return;
+    }
+
+    for (e, l) in &lines {
+        if l.cell() == (cursor.x, cursor.y) {
+            commands.entity(e).despawn();
+        }
+    }
+}
+
+// -----------------------------
+// Grid solving
+// -----------------------------
+
+pub(super) fn rebuild_grids(
+    layout: Res<Layout>,
+    mut grids: ResMut<PowerGrids>,
+    lines: Query<&PowerLine>,
+    machines: Query<(Entity, &Transform), With<Machine>>,
+) {
+    if !layout.is_changed() {
+        return;
+    }
+
+    let cells = lines.iter().map(PowerLine::cell);
+    let members = machines.iter().map(|(e, t)| (e, world_cell(t.translation)));
+    grids.grids = connect_networks(cells, members)
+        .into_iter()
+        .map(|(_, members)| members)
+        .collect();
+}
+
+/// Balances generation against demand on each grid.
+///
+/// Surplus charges power cells; a shortfall is covered from them, and whatever is
+/// still missing is shared out as a supply fraction every consumer on the grid gets.
+/// Machines that draw nothing are left unpowered; generators record their output instead.
+pub(super) fn solve_power(
+    time: Res<Time>,
+    grids: Res<PowerGrids>,
+    mut balance: ResMut<PowerBalance>,
+    mut consumers: Query<(&Machine, &Synergies, &Wear, &mut Powered, &mut Output)>,
+    mut cells: Query<&mut PowerCell>,
+    running: Query<(), (With<Machine>, Without<Disabled>)>,
+) {
+    let dt = time.delta_secs();
+    if dt <= 0.0 {
+        return;
+    }
+
+    // anything not reached below is off-grid
+    for (.., mut p, mut out) in &mut consumers {
+        p.0 = 0.0;
+        out.0 = 0.0;
+    }
+
+    *balance = PowerBalance::default();
+
+    for grid in &grids.grids {
+        let mut generation = 0.0;
+        let mut demand = 0.0;
+
+        for &e in grid {
+            let Ok((m, syn, wear, ..)) = consumers.get(e) else {
+                continue;
+            };
+            if running.get(e).is_err() {
+                continue;
+            }
+            match m.kind {
+                Tool::Generator => generation += syn.strength(m) * wear.factor() * dt,
+                _ => demand += power_draw(m) * dt,
+            }
+        }
+
+        let mut shortfall = demand - generation;
+        for &e in grid {
+            let Ok(mut cell) = cells.get_mut(e) else {
+                continue;
+            };
+            if shortfall > 0.0 {
+                let used = shortfall.min(cell.charge);
+                cell.charge -= used;
+                shortfall -= used;
+            } else {
+                let stored = (-shortfall).min(cell.capacity - cell.charge);
+                cell.charge += stored;
+                shortfall += stored;
+            }
+            balance.stored += cell.charge;
+            balance.capacity += cell.capacity;
+        }
+
+        let supply = if demand > 0.0 {
+            (1.0 - shortfall.max(0.0) / demand).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        // whatever surplus the cells couldn't take went unused
+        let output = if generation > 0.0 {
+            (1.0 - (-shortfall).max(0.0) / generation).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        for &e in grid {
+            let Ok((m, .., mut p, mut out)) = consumers.get_mut(e) else {
+                continue;
+            };
+            if m.kind == Tool::Generator {
+                out.0 = output;
+            } else if power_draw(m) > 0.0 {
+                p.0 = supply;
+            }
+        }
+
+        balance.generation += generation / dt;
+        balance.demand += demand / dt;
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+pub(super) fn update_readout(
+    balance: Res<PowerBalance>,
+    mut q: Query<&mut Text, With<PowerReadout>>,
+) {
+    if !balance.is_changed() {
+        return;
+    }
+    let Ok(mut text) = q.single_mut() else { return };
+
+    let net = balance.generation - balance.demand;
+    let state = if net >= 0.0 {
+        "ok"
+    } else if balance.stored > 0.0 {
+        "on reserve"
+    } else {
+        "BROWNOUT"
+    };
+    *text = Text::new(format!(
+        "Power: {:.1} / {:.1} ({:+.1}) {}  |  Cells {:.0}/{:.0}",
+        balance.generation, balance.demand, net, state, balance.stored, balance.capacity
+    ));
+}
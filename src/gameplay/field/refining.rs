@@ -0,0 +1,302 @@
+﻿use std::fmt;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::inventory::{CRYSTAL, Inventory};
+use super::overload::Disabled;
+use super::{CursorCell, Drive, FieldGrid, Machine, world_cell};
+use crate::state::LoadingProgress;
+
+const RECIPES_PATH: &str = "farm.recipes.ron";
+
+const BAR_WIDTH: f32 = 0.6;
+
+// -----------------------------
+// Recipe assets
+// -----------------------------
+
+#[derive(Clone, Debug, Deserialize)]
+pub(super) struct Recipe {
+    pub(super) id: String,
+    pub(super) name: String,
+    pub(super) inputs: Vec<(String, u32)>,
+    pub(super) outputs: Vec<(String, u32)>,
+    /// Seconds per batch for a tier-1 refiner at full drive.
+    pub(super) time: f32,
+    /// Aether the refiner's own cell must hold for work to progress.
+    #[serde(default)]
+    pub(super) min_aether: f32,
+}
+
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub(super) struct RecipeBook {
+    recipes: Vec<Recipe>,
+}
+
+impl RecipeBook {
+    pub(super) fn get(&self, id: &str) -> Option<&Recipe> {
+        self.recipes.iter().find(|r| r.id == id)
+    }
+
+    /// The recipe after `id` in the book, wrapping through "no recipe".
+    fn next_after(&self, id: Option<&str>) -> Option<&Recipe> {
+        match id.and_then(|id| self.recipes.iter().position(|r| r.id == id)) {
+            Some(i) => self.recipes.get(i + 1),
+            None => self.recipes.first(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(super) enum RecipeLoadError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for RecipeLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecipeLoadError::Io(e) => write!(f, "could not read recipes: {e}"),
+            RecipeLoadError::Ron(e) => write!(f, "could not parse recipes: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RecipeLoadError {}
+
+impl From<std::io::Error> for RecipeLoadError {
+    fn from(e: std::io::Error) -> Self {
+        RecipeLoadError::Io(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for RecipeLoadError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        RecipeLoadError::Ron(e)
+    }
+}
+
+#[derive(Default, TypePath)]
+pub(super) struct RecipeLoader;
+
+impl AssetLoader for RecipeLoader {
+    type Asset = RecipeBook;
+    type Settings = ();
+    type Error = RecipeLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<RecipeBook, RecipeLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["recipes.ron"]
+    }
+}
+
+/// Handle to the recipe book every refiner picks from.
+#[derive(Resource)]
+pub(super) struct Recipes(pub(super) Handle<RecipeBook>);
+
+// -----------------------------
+// Components
+// -----------------------------
+
+/// Works one batch of its recipe at a time: inputs are taken when a batch starts,
+/// outputs land in the machine's inventory when it finishes.
+#[derive(Component, Clone, Debug, Default, Serialize, Deserialize)]
+pub(super) struct Refiner {
+    pub(super) recipe: Option<String>,
+    pub(super) progress: f32,
+    running: bool,
+}
+
+/// Child bar whose width follows the batch progress.
+#[derive(Component)]
+pub(super) struct ProgressBar;
+
+pub(super) fn spawn_progress_bar(
+    parent: &mut ChildSpawnerCommands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    parent.spawn((
+        Mesh3d(meshes.add(Cuboid::new(BAR_WIDTH, 0.06, 0.06))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgb(0.4, 1.0, 0.4),
+            emissive: Color::srgb(0.1, 0.5, 0.1).into(),
+            unlit: true,
+            ..default()
+        })),
+        Transform::from_xyz(0.0, 0.5, 0.36).with_scale(Vec3::new(0.0, 1.0, 1.0)),
+        ProgressBar,
+    ));
+}
+
+// -----------------------------
+// Setup
+// -----------------------------
+
+pub(super) fn load_recipes(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(Recipes(assets.load(RECIPES_PATH)));
+}
+
+pub(super) fn report_loading(
+    assets: Res<AssetServer>,
+    recipes: Res<Recipes>,
+    mut progress: ResMut<LoadingProgress>,
+) {
+    progress.report_asset("recipes", &assets, &recipes.0);
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+/// V cycles the recipe of the refiner under the cursor. Switching drops the batch in progress.
+pub(super) fn select_recipe(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    recipes: Res<Recipes>,
+    books: Res<Assets<RecipeBook>>,
+    mut q: Query<(&Transform, &mut Refiner)>,
+) {
+    if !keys.just_pressed(KeyCode::KeyV) {
+        return;
+    }
+    let Some(book) = books.get(&recipes.0) else {
+        warn!("recipes are not loaded");
+        return;
+    };
+
+    for (t, mut refiner) in &mut q {
+        if world_cell(t.translation) != (cursor.x, cursor.y) {
+            continue;
+        }
+        let next = book.next_after(refiner.recipe.as_deref());
+        *refiner = Refiner {
+            recipe: next.map(|r| r.id.clone()),
+            ..default()
+        };
+        match next {
+            Some(r) => info!("refiner recipe: {}", r.name),
+            None => info!("refiner idle"),
+        }
+    }
+}
+
+// -----------------------------
+// Simulation
+// -----------------------------
+
+/// Takes a batch's inputs from the inventory, making up missing crystal from the
+/// field around (x, y). Takes nothing unless everything is there.
+fn take_inputs(
+    recipe: &Recipe,
+    inv: &mut Inventory,
+    grid: &mut FieldGrid,
+    x: i32,
+    y: i32,
+    r: i32,
+) -> bool {
+    let mut from_field = 0;
+    for (item, n) in &recipe.inputs {
+        let have = inv.count(item);
+        if item == CRYSTAL {
+            from_field += n.saturating_sub(have);
+        } else if have < *n {
+            return false;
+        }
+    }
+    if !grid.take_crystal_around(x, y, r, from_field as f32) {
+        return false;
+    }
+
+    for (item, n) in &recipe.inputs {
+        let n = if item == CRYSTAL {
+            (*n).min(inv.count(item))
+        } else {
+            *n
+        };
+        inv.take(item, n);
+    }
+    true
+}
+
+pub(super) fn run_refiners(
+    time: Res<Time>,
+    recipes: Res<Recipes>,
+    books: Res<Assets<RecipeBook>>,
+    mut grid: ResMut<FieldGrid>,
+    mut q: Query<(&Transform, &Machine, &Drive, &mut Refiner, &mut Inventory), Without<Disabled>>,
+) {
+    let Some(book) = books.get(&recipes.0) else {
+        return;
+    };
+    let dt = time.delta_secs();
+
+    for (t, m, drive, mut refiner, mut inv) in &mut q {
+        let Some(recipe) = refiner.recipe.as_deref().and_then(|id| book.get(id)) else {
+            continue;
+        };
+        let (x, y) = world_cell(t.translation);
+        if !grid.in_bounds(x, y) || drive.0 <= 0.0 {
+            continue;
+        }
+        // stalls, keeping its progress, while the field is too thin
+        if grid.aether[grid.idx(x, y)] < recipe.min_aether {
+            continue;
+        }
+
+        if !refiner.running {
+            if !take_inputs(recipe, &mut inv, &mut grid, x, y, m.radius) {
+                continue;
+            }
+            refiner.running = true;
+            refiner.progress = 0.0;
+        }
+
+        refiner.progress = (refiner.progress + drive.0 * m.strength * dt / recipe.time).min(1.0);
+        if refiner.progress < 1.0 {
+            continue;
+        }
+
+        // finished batches wait for room
+        let produced: u32 = recipe.outputs.iter().map(|(_, n)| n).sum();
+        if inv.room() < produced {
+            continue;
+        }
+        for (item, n) in &recipe.outputs {
+            inv.add(item, *n);
+        }
+        refiner.running = false;
+        refiner.progress = 0.0;
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+pub(super) fn update_progress_bars(
+    refiners: Query<(&Refiner, &Children), Changed<Refiner>>,
+    mut bars: Query<&mut Transform, With<ProgressBar>>,
+) {
+    for (refiner, children) in &refiners {
+        for child in children.iter() {
+            if let Ok(mut t) = bars.get_mut(child) {
+                t.scale.x = refiner.progress;
+                t.translation.x = (refiner.progress - 1.0) * BAR_WIDTH * 0.5;
+            }
+        }
+    }
+}
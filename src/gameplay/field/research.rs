@@ -0,0 +1,417 @@
+﻿use std::collections::{BTreeSet, VecDeque};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::economy::Funds;
+use super::machines::machine_def;
+use super::{FieldGrid, Tool};
+use crate::state::InSession;
+
+// -----------------------------
+// Definitions
+// -----------------------------
+
+/// Tools available before any research.
+// Field machines need power to run, so a generator and lines come with them.
+const STARTING_TOOLS: &[Tool] = &[
+    Tool::Emitter,
+    Tool::Sink,
+    Tool::Stabilizer,
+    Tool::Pipe,
+    Tool::Generator,
+    Tool::PowerLine,
+];
+
+pub(super) enum Unlock {
+    Tool(Tool),
+    /// Upgrades up to this tier (0-based) for every machine.
+    MaxTier(u8),
+}
+
+/// One node of the research tree. Its costs are paid when work on it starts; crystal is
+/// drawn from anywhere on the field.
+pub(super) struct ResearchDef {
+    pub(super) id: &'static str,
+    pub(super) name: &'static str,
+    pub(super) requires: &'static [&'static str],
+    pub(super) credits: f32,
+    pub(super) crystal: f32,
+    /// Seconds of work.
+    pub(super) time: f32,
+    pub(super) unlocks: &'static [Unlock],
+}
+
+pub(super) static RESEARCH: &[ResearchDef] = &[
+    ResearchDef {
+        id: "power",
+        name: "Power Storage",
+        requires: &[],
+        credits: 40.0,
+        crystal: 0.0,
+        time: 20.0,
+        unlocks: &[Unlock::Tool(Tool::PowerCell)],
+    },
+    ResearchDef {
+        id: "storage",
+        name: "Storage",
+        requires: &[],
+        credits: 30.0,
+        crystal: 0.0,
+        time: 15.0,
+        unlocks: &[Unlock::Tool(Tool::Chest)],
+    },
+    ResearchDef {
+        id: "improved",
+        name: "Improved Fabrication",
+        requires: &[],
+        credits: 50.0,
+        crystal: 10.0,
+        time: 30.0,
+        unlocks: &[Unlock::MaxTier(1)],
+    },
+    ResearchDef {
+        id: "signals",
+        name: "Signal Logic",
+        requires: &["power"],
+        credits: 60.0,
+        crystal: 10.0,
+        time: 30.0,
+        unlocks: &[
+            Unlock::Tool(Tool::Sensor),
+            Unlock::Tool(Tool::Logic),
+            Unlock::Tool(Tool::SignalWire),
+        ],
+    },
+    ResearchDef {
+        id: "maintenance",
+        name: "Maintenance",
+        requires: &["power"],
+        credits: 70.0,
+        crystal: 20.0,
+        time: 30.0,
+        unlocks: &[Unlock::Tool(Tool::MaintenanceBay)],
+    },
+    ResearchDef {
+        id: "refining",
+        name: "Refining",
+        requires: &["storage"],
+        credits: 100.0,
+        crystal: 30.0,
+        time: 40.0,
+        unlocks: &[Unlock::Tool(Tool::Refiner)],
+    },
+    ResearchDef {
+        id: "logistics",
+        name: "Logistics",
+        requires: &["storage", "power"],
+        credits: 80.0,
+        crystal: 15.0,
+        time: 30.0,
+        unlocks: &[Unlock::Tool(Tool::Conveyor), Unlock::Tool(Tool::Inserter)],
+    },
+    ResearchDef {
+        id: "automation",
+        name: "Automation",
+        requires: &["signals"],
+        credits: 120.0,
+        crystal: 25.0,
+        time: 45.0,
+        unlocks: &[Unlock::Tool(Tool::Controller)],
+    },
+    ResearchDef {
+        id: "advanced",
+        name: "Advanced Fabrication",
+        requires: &["improved", "refining"],
+        credits: 150.0,
+        crystal: 40.0,
+        time: 60.0,
+        unlocks: &[Unlock::MaxTier(2)],
+    },
+    ResearchDef {
+        id: "drones",
+        name: "Drones",
+        requires: &["logistics", "maintenance"],
+        credits: 200.0,
+        crystal: 50.0,
+        time: 60.0,
+        unlocks: &[Unlock::Tool(Tool::DroneBay), Unlock::Tool(Tool::Wall)],
+    },
+];
+
+fn research_def(id: &str) -> Option<&'static ResearchDef> {
+    RESEARCH.iter().find(|r| r.id == id)
+}
+
+// -----------------------------
+// Resources + Components
+// -----------------------------
+
+/// What has been researched, what is being worked on and what is queued, by node id.
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+pub(super) struct Research {
+    done: BTreeSet<String>,
+    /// Node in progress and seconds of work put into it. Its costs are already paid.
+    current: Option<(String, f32)>,
+    queue: VecDeque<String>,
+}
+
+impl Research {
+    pub(super) fn with_done(ids: &[String]) -> Self {
+        Self {
+            done: ids.iter().cloned().collect(),
+            ..default()
+        }
+    }
+
+    fn is_done(&self, id: &str) -> bool {
+        self.done.contains(id)
+    }
+
+    fn is_queued(&self, id: &str) -> bool {
+        self.current.as_ref().is_some_and(|(c, _)| c == id) || self.queue.iter().any(|q| q == id)
+    }
+
+    fn unlocked(&self) -> impl Iterator<Item = &'static Unlock> {
+        RESEARCH
+            .iter()
+            .filter(|r| self.is_done(r.id))
+            .flat_map(|r| r.unlocks)
+    }
+
+    pub(super) fn has_tool(&self, tool: Tool) -> bool {
+        STARTING_TOOLS.contains(&tool)
+            || self
+                .unlocked()
+                .any(|u| matches!(u, Unlock::Tool(t) if *t == tool))
+    }
+
+    /// Highest tier machines may be upgraded to.
+    pub(super) fn max_tier(&self) -> u8 {
+        self.unlocked()
+            .filter_map(|u| match u {
+                Unlock::MaxTier(t) => Some(*t),
+                Unlock::Tool(_) => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Prerequisites of `def` that are neither done nor coming earlier in the queue.
+    fn missing(&self, def: &ResearchDef) -> Vec<&'static str> {
+        def.requires
+            .iter()
+            .copied()
+            .filter(|r| !self.is_done(r) && !self.is_queued(r))
+            .collect()
+    }
+
+    /// Queues `def`, or takes it (and anything queued after it that needs it) off the queue.
+    fn toggle_queued(&mut self, def: &'static ResearchDef) {
+        if self.is_done(def.id) {
+            return;
+        }
+        if self.queue.iter().any(|q| q == def.id) {
+            let mut dropped = vec![def.id.to_string()];
+            self.queue.retain(|q| {
+                let needs_dropped = research_def(q)
+                    .is_some_and(|d| d.requires.iter().any(|r| dropped.iter().any(|x| x == r)));
+                if q == def.id || needs_dropped {
+                    dropped.push(q.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+            info!("unqueued {}", def.name);
+            return;
+        }
+        if self.is_queued(def.id) {
+            info!("{} is already in progress", def.name);
+            return;
+        }
+        let missing = self.missing(def);
+        if !missing.is_empty() {
+            info!("{} needs {} first", def.name, missing.join(", "));
+            return;
+        }
+        self.queue.push_back(def.id.to_string());
+        info!("queued {}", def.name);
+    }
+}
+
+/// Open research browser and the selected row.
+#[derive(Resource)]
+pub(super) struct ResearchBrowser {
+    selected: usize,
+    panel: Entity,
+}
+
+#[derive(Component)]
+pub(super) struct ResearchText;
+
+// -----------------------------
+// Input
+// -----------------------------
+
+/// T opens the research browser; T or Esc closes it.
+pub(super) fn toggle_browser(
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut commands: Commands,
+    browser: Option<Res<ResearchBrowser>>,
+) {
+    if let Some(browser) = browser {
+        if keys.any_just_pressed([KeyCode::KeyT, KeyCode::Escape]) {
+            commands.entity(browser.panel).despawn();
+            commands.remove_resource::<ResearchBrowser>();
+            // closing the browser shouldn't also open the pause menu
+            keys.clear_just_pressed(KeyCode::Escape);
+        }
+        return;
+    }
+    if !keys.just_pressed(KeyCode::KeyT) {
+        return;
+    }
+
+    let panel = commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(16.0),
+                top: Val::Px(64.0),
+                width: Val::Px(560.0),
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.02, 0.02, 0.06, 0.9)),
+            children![(
+                Text::new(""),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                ResearchText,
+            )],
+            DespawnOnExit(InSession),
+        ))
+        .id();
+    commands.insert_resource(ResearchBrowser { selected: 0, panel });
+}
+
+/// Up/Down pick a node, Enter queues or unqueues it.
+pub(super) fn browse_research(
+    keys: Res<ButtonInput<KeyCode>>,
+    browser: Option<ResMut<ResearchBrowser>>,
+    mut research: ResMut<Research>,
+) {
+    let Some(mut browser) = browser else {
+        return;
+    };
+    let last = RESEARCH.len() - 1;
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        browser.selected = browser.selected.saturating_sub(1);
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        browser.selected = (browser.selected + 1).min(last);
+    }
+    if keys.just_pressed(KeyCode::Enter) {
+        research.toggle_queued(&RESEARCH[browser.selected]);
+    }
+}
+
+// -----------------------------
+// Simulation (fixed tick)
+// -----------------------------
+
+/// Works on the node at the head of the queue. A node starts once its costs can be
+/// paid; until then the queue waits.
+pub(super) fn run_research(
+    time: Res<Time>,
+    mut research: ResMut<Research>,
+    mut funds: ResMut<Funds>,
+    mut grid: ResMut<FieldGrid>,
+) {
+    if research.current.is_none() {
+        let Some(def) = research.queue.front().and_then(|id| research_def(id)) else {
+            return;
+        };
+        if funds.0 < def.credits {
+            return;
+        }
+        let (cx, cy, everywhere) = (grid.w / 2, grid.h / 2, grid.w + grid.h);
+        if !grid.take_crystal_around(cx, cy, everywhere, def.crystal) {
+            return;
+        }
+        funds.0 -= def.credits;
+        research.queue.pop_front();
+        research.current = Some((def.id.to_string(), 0.0));
+        info!("started researching {}", def.name);
+    }
+
+    let Some((id, elapsed)) = research.current.as_mut() else {
+        return;
+    };
+    *elapsed += time.delta_secs();
+    let Some(def) = research_def(id) else {
+        research.current = None;
+        return;
+    };
+    if *elapsed >= def.time {
+        research.current = None;
+        research.done.insert(def.id.to_string());
+        let tools: Vec<&str> = def
+            .unlocks
+            .iter()
+            .filter_map(|u| match u {
+                Unlock::Tool(t) => machine_def(*t).map(|d| d.name),
+                Unlock::MaxTier(_) => None,
+            })
+            .collect();
+        info!("researched {}; unlocked {}", def.name, tools.join(", "));
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+pub(super) fn update_browser_text(
+    research: Res<Research>,
+    funds: Res<Funds>,
+    browser: Option<Res<ResearchBrowser>>,
+    mut q: Query<&mut Text, With<ResearchText>>,
+) {
+    let Some(browser) = browser else {
+        return;
+    };
+    let Ok(mut text) = q.single_mut() else { return };
+
+    let mut lines = vec![format!(
+        "Research ({:.0} credits) - Up/Down select, Enter queue/unqueue, T close",
+        funds.0
+    )];
+    for (i, def) in RESEARCH.iter().enumerate() {
+        let status = if research.is_done(def.id) {
+            "done".to_string()
+        } else if let Some((_, elapsed)) = research.current.as_ref().filter(|(c, _)| c == def.id) {
+            format!("{:.0}%", elapsed / def.time * 100.0)
+        } else if let Some(pos) = research.queue.iter().position(|q| q == def.id) {
+            format!("queued #{}", pos + 1)
+        } else if research.missing(def).is_empty() {
+            "available".to_string()
+        } else {
+            format!("needs {}", research.missing(def).join(", "))
+        };
+        let marker = if i == browser.selected { ">" } else { " " };
+        lines.push(format!(
+            "{marker} {} [{status}]  {:.0} cr, {:.0} crystal, {:.0}s",
+            def.name, def.credits, def.crystal, def.time
+        ));
+    }
+
+    let line = lines.join("\n");
+    if text.0 != line {
+        text.0 = line;
+    }
+}
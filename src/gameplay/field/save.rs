@@ -0,0 +1,444 @@
+﻿use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::battery::AetherStore;
+use super::controller::Controller;
+use super::drones::{self, Wall};
+use super::economy::{Funds, STARTING_FUNDS};
+use super::influence::Influence;
+use super::inventory::Inventory;
+use super::logistics::{self, Conveyor};
+use super::offline;
+use super::overload::Integrity;
+use super::pipes::{self, Pipe};
+use super::power::{self, PowerCell, PowerLine};
+use super::refining::Refiner;
+use super::research::Research;
+use super::scenario::ScenarioRun;
+use super::signals::{self, Gate, Receiver, Sensor, SignalWire};
+use super::waveform::Waveform;
+use super::wear::Wear;
+use super::{FieldGrid, Machine, Tool, machines, world_cell};
+use crate::settings::app_dir;
+use crate::state::StartMode;
+
+const SAVE_FILE: &str = "farm.sav.ron";
+
+/// `<config dir>/crystalfarm/farm.sav.ron`, next to the settings.
+fn save_path() -> Option<PathBuf> {
+    Some(app_dir()?.join(SAVE_FILE))
+}
+
+// -----------------------------
+// Save format
+// -----------------------------
+
+#[derive(Serialize, Deserialize)]
+struct SavedMachine {
+    kind: Tool,
+    x: i32,
+    y: i32,
+    tier: u8,
+    integrity: f32,
+    #[serde(default)]
+    stored: f32,
+    #[serde(default)]
+    charge: f32,
+    #[serde(default)]
+    waveform: Option<Waveform>,
+    #[serde(default)]
+    influence: Influence,
+    #[serde(default)]
+    receiver: Receiver,
+    #[serde(default)]
+    sensor: Option<Sensor>,
+    #[serde(default)]
+    gate: Option<Gate>,
+    #[serde(default)]
+    wear: f32,
+    #[serde(default)]
+    refiner: Option<Refiner>,
+    #[serde(default)]
+    inventory: Option<Inventory>,
+    #[serde(default)]
+    script: Option<String>,
+    /// Cells of the machines a controller is linked to, in link order.
+    #[serde(default)]
+    links: Vec<(i32, i32)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedConveyor {
+    x: i32,
+    y: i32,
+    facing: u8,
+    speed: f32,
+    #[serde(default)]
+    item: Option<String>,
+}
+
+fn starting_funds() -> f32 {
+    STARTING_FUNDS
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    w: i32,
+    h: i32,
+    aether: Vec<f32>,
+    crystal: Vec<f32>,
+    #[serde(default)]
+    purity: Vec<f32>,
+    #[serde(default = "starting_funds")]
+    funds: f32,
+    #[serde(default)]
+    research: Research,
+    machines: Vec<SavedMachine>,
+    pipes: Vec<(i32, i32)>,
+    lines: Vec<(i32, i32)>,
+    #[serde(default)]
+    wires: Vec<(i32, i32)>,
+    #[serde(default)]
+    conveyors: Vec<SavedConveyor>,
+    #[serde(default)]
+    walls: Vec<(i32, i32)>,
+    /// The scenario in progress, if any, with its clock and objectives.
+    #[serde(default)]
+    scenario: Option<ScenarioRun>,
+    /// Unix time of the save, for offline progress.
+    #[serde(default)]
+    saved_at: u64,
+}
+
+/// Everything the player builds; cleared before a save is loaded.
+pub(super) type Buildings = Or<(
+    With<Machine>,
+    With<Pipe>,
+    With<PowerLine>,
+    With<SignalWire>,
+    With<Conveyor>,
+    With<Wall>,
+)>;
+
+/// Optional per-machine state, looked up by entity when saving.
+#[derive(SystemParam)]
+pub(super) struct MachineState<'w, 's> {
+    stores: Query<'w, 's, &'static AetherStore>,
+    cells: Query<'w, 's, &'static PowerCell>,
+    waves: Query<'w, 's, &'static Waveform>,
+    receivers: Query<'w, 's, &'static Receiver>,
+    sensors: Query<'w, 's, &'static Sensor>,
+    gates: Query<'w, 's, &'static Gate>,
+    controllers: Query<'w, 's, &'static Controller>,
+    refiners: Query<'w, 's, &'static Refiner>,
+    inventories: Query<'w, 's, &'static Inventory>,
+}
+
+#[derive(SystemParam)]
+pub(super) struct Conduits<'w, 's> {
+    pipes: Query<'w, 's, &'static Pipe>,
+    lines: Query<'w, 's, &'static PowerLine>,
+    wires: Query<'w, 's, &'static SignalWire>,
+    conveyors: Query<'w, 's, &'static Conveyor>,
+    walls: Query<'w, 's, &'static Wall>,
+}
+
+// -----------------------------
+// Systems
+// -----------------------------
+
+#[allow(clippy::too_many_arguments)]
+pub(super) fn save_game(
+    keys: Res<ButtonInput<KeyCode>>,
+    grid: Res<FieldGrid>,
+    funds: Res<Funds>,
+    research: Res<Research>,
+    run: Option<Res<ScenarioRun>>,
+    machines: Query<(Entity, &Transform, &Machine, &Integrity, &Influence, &Wear)>,
+    state: MachineState,
+    conduits: Conduits,
+) {
+    if !keys.just_pressed(KeyCode::F5) {
+        return;
+    }
+
+    let file = SaveFile {
+        w: grid.w,
+        h: grid.h,
+        aether: grid.aether.clone(),
+        crystal: grid.crystal.clone(),
+        purity: grid.purity.clone(),
+        funds: funds.0,
+        research: research.clone(),
+        machines: machines
+            .iter()
+            .map(|(e, t, m, integrity, influence, wear)| {
+                let (x, y) = world_cell(t.translation);
+                let controller = state.controllers.get(e).ok();
+                SavedMachine {
+                    kind: m.kind,
+                    x,
+                    y,
+                    tier: m.tier,
+                    integrity: integrity.0,
+                    stored: state.stores.get(e).map_or(0.0, |s| s.stored),
+                    charge: state.cells.get(e).map_or(0.0, PowerCell::charge),
+                    waveform: state.waves.get(e).ok().copied(),
+                    influence: *influence,
+                    receiver: state.receivers.get(e).copied().unwrap_or_default(),
+                    sensor: state.sensors.get(e).ok().copied(),
+                    gate: state.gates.get(e).ok().copied(),
+                    wear: wear.0,
+                    refiner: state.refiners.get(e).ok().cloned(),
+                    inventory: state.inventories.get(e).ok().cloned(),
+                    script: controller.map(|c| c.source().to_string()),
+                    links: controller
+                        .into_iter()
+                        .flat_map(|c| &c.links)
+                        .filter_map(|&l| machines.get(l).ok())
+                        .map(|(_, t, ..)| world_cell(t.translation))
+                        .collect(),
+                }
+            })
+            .collect(),
+        pipes: conduits.pipes.iter().map(Pipe::cell).collect(),
+        lines: conduits.lines.iter().map(PowerLine::cell).collect(),
+        wires: conduits.wires.iter().map(SignalWire::cell).collect(),
+        conveyors: conduits
+            .conveyors
+            .iter()
+            .map(|c| {
+                let (x, y) = c.cell();
+                SavedConveyor {
+                    x,
+                    y,
+                    facing: c.facing,
+                    speed: c.speed,
+                    item: c.item.as_ref().map(|(i, _)| i.clone()),
+                }
+            })
+            .collect(),
+        walls: conduits.walls.iter().map(Wall::cell).collect(),
+        scenario: run.map(|r| r.clone()),
+        saved_at: offline::now_secs(),
+    };
+
+    let text = match ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default()) {
+        Ok(text) => text,
+        Err(e) => {
+            error!("failed to serialize save: {e}");
+            return;
+        }
+    };
+    let Some(path) = save_path() else {
+        warn!("no config directory; the farm is not saved");
+        return;
+    };
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, text));
+    match written {
+        Ok(()) => info!("saved farm to {}", path.display()),
+        Err(e) => error!("failed to write {}: {e}", path.display()),
+    }
+}
+
+/// Everything loading a save replaces.
+#[derive(SystemParam)]
+pub(super) struct FarmLoader<'w, 's> {
+    commands: Commands<'w, 's>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<StandardMaterial>>,
+    grid: ResMut<'w, FieldGrid>,
+    funds: ResMut<'w, Funds>,
+    research: ResMut<'w, Research>,
+    existing: Query<'w, 's, Entity, Buildings>,
+}
+
+impl FarmLoader<'_, '_> {
+    fn load(&mut self) {
+        let Some(path) = save_path() else {
+            error!("no config directory to load a farm from");
+            return;
+        };
+        let file: SaveFile = match fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| ron::from_str(&text).map_err(|e| e.to_string()))
+        {
+            Ok(file) => file,
+            Err(e) => {
+                error!("failed to load {}: {e}", path.display());
+                return;
+            }
+        };
+        if (file.w, file.h) != (self.grid.w, self.grid.h) {
+            error!(
+                "save is for a {}x{} field, this one is {}x{}",
+                file.w, file.h, self.grid.w, self.grid.h
+            );
+            return;
+        }
+        let cells = (file.w * file.h) as usize;
+        if file.aether.len() != cells || file.crystal.len() != cells {
+            error!(
+                "save has {} aether and {} crystal cells, expected {cells}",
+                file.aether.len(),
+                file.crystal.len()
+            );
+            return;
+        }
+
+        for e in &self.existing {
+            self.commands.entity(e).despawn();
+        }
+
+        self.grid.aether = file.aether;
+        self.grid.crystal = file.crystal;
+        // saves from before grading have no purity; their crystal counts as rough
+        if file.purity.len() == self.grid.purity.len() {
+            self.grid.purity = file.purity;
+        } else {
+            self.grid.purity.fill(0.0);
+        }
+        self.funds.0 = file.funds;
+        *self.research = file.research;
+        self.grid.pressure.fill(0.0);
+        match file.scenario {
+            Some(run) => self.commands.insert_resource(run),
+            None => self.commands.remove_resource::<ScenarioRun>(),
+        }
+
+        // controllers link by entity, so they are hooked up once everything exists
+        let mut spawned = HashMap::new();
+        let mut controllers = Vec::new();
+
+        for m in file.machines {
+            if machines::machine_def(m.kind).is_none() {
+                continue;
+            }
+            let e = machines::spawn_machine(
+                &mut self.commands,
+                &mut self.meshes,
+                &mut self.materials,
+                m.kind,
+                m.tier,
+                m.x,
+                m.y,
+            );
+            let (stored, charge) = (m.stored, m.charge);
+            let mut entity = self.commands.entity(e);
+            entity.insert((
+                Integrity(m.integrity),
+                Wear(m.wear),
+                m.influence,
+                m.receiver,
+            ));
+            if let Some(wave) = m.waveform {
+                entity.insert(wave);
+            }
+            if let Some(sensor) = m.sensor {
+                entity.insert(sensor);
+            }
+            if let Some(gate) = m.gate {
+                entity.insert(gate);
+            }
+            if let Some(refiner) = m.refiner {
+                entity.insert(refiner);
+            }
+            if let Some(inventory) = m.inventory {
+                entity.insert(inventory);
+            }
+            if let Some(script) = m.script {
+                controllers.push((e, script, m.links));
+            }
+            spawned.insert((m.x, m.y), e);
+            entity.queue(move |mut entity: EntityWorldMut| {
+                if let Some(mut store) = entity.get_mut::<AetherStore>() {
+                    store.stored = stored.min(store.capacity);
+                }
+                if let Some(mut cell) = entity.get_mut::<PowerCell>() {
+                    cell.set_charge(charge);
+                }
+            });
+        }
+        for (e, script, links) in controllers {
+            let mut c = Controller::new(script);
+            c.links = links
+                .iter()
+                .filter_map(|l| spawned.get(l))
+                .copied()
+                .collect();
+            self.commands.entity(e).insert(c);
+        }
+        for (x, y) in file.pipes {
+            pipes::spawn_pipe(
+                &mut self.commands,
+                &mut self.meshes,
+                &mut self.materials,
+                x,
+                y,
+            );
+        }
+        for (x, y) in file.lines {
+            power::spawn_line(
+                &mut self.commands,
+                &mut self.meshes,
+                &mut self.materials,
+                x,
+                y,
+            );
+        }
+        for (x, y) in file.wires {
+            signals::spawn_wire(
+                &mut self.commands,
+                &mut self.meshes,
+                &mut self.materials,
+                x,
+                y,
+            );
+        }
+        for c in file.conveyors {
+            logistics::spawn_conveyor(
+                &mut self.commands,
+                &mut self.meshes,
+                &mut self.materials,
+                c.x,
+                c.y,
+                c.facing,
+                c.speed,
+                c.item,
+            );
+        }
+        for (x, y) in file.walls {
+            drones::spawn_wall(
+                &mut self.commands,
+                &mut self.meshes,
+                &mut self.materials,
+                x,
+                y,
+            );
+        }
+
+        offline::queue_catch_up(&mut self.commands, file.saved_at);
+        info!("loaded farm from {}", path.display());
+    }
+}
+
+pub(super) fn load_game(keys: Res<ButtonInput<KeyCode>>, mut farm: FarmLoader) {
+    if keys.just_pressed(KeyCode::F9) {
+        farm.load();
+    }
+}
+
+/// Loads the save when the game was started with Load from the main menu.
+pub(super) fn load_on_start(start: Res<StartMode>, mut farm: FarmLoader) {
+    if *start == StartMode::Load {
+        farm.load();
+    }
+}
@@ -0,0 +1,547 @@
+﻿use std::fmt;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::contracts::Contracts;
+use super::drones;
+use super::economy::{Funds, Grade};
+use super::hud::{HudPanel, spawn_readout};
+use super::inventory::{CRYSTAL, Inventory};
+use super::research::Research;
+use super::save::Buildings;
+use super::{FieldGrid, Tool, machines, pipes, power};
+use crate::state::{InSession, LoadingProgress, StartMode};
+
+/// Scenarios in play order; F2 moves on to the next one.
+const SCENARIOS: &[&str] = &[
+    "scenarios/first_harvest.scenario.ron",
+    "scenarios/calm_waters.scenario.ron",
+];
+
+// -----------------------------
+// Scenario assets
+// -----------------------------
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub(super) enum Objective {
+    /// Crystal on the field plus crystal of any grade in storage.
+    ReachCrystal {
+        amount: f32,
+    },
+    /// No cell's aether reaches `max` for `seconds` in a row.
+    KeepAetherBelow {
+        max: f32,
+        seconds: f32,
+    },
+    EarnCredits {
+        amount: f32,
+    },
+}
+
+impl fmt::Display for Objective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Objective::ReachCrystal { amount } => write!(f, "Hold {amount:.0} crystal"),
+            Objective::KeepAetherBelow { max, seconds } => {
+                write!(f, "Keep aether below {max:.1} for {seconds:.0}s")
+            }
+            Objective::EarnCredits { amount } => write!(f, "Have {amount:.0} credits"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SeedCell {
+    x: i32,
+    y: i32,
+    #[serde(default)]
+    aether: f32,
+    #[serde(default)]
+    crystal: f32,
+    #[serde(default)]
+    purity: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlacedMachine {
+    kind: Tool,
+    x: i32,
+    y: i32,
+    #[serde(default)]
+    tier: u8,
+}
+
+/// Starting state and goals of one play session.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub(super) struct Scenario {
+    name: String,
+    #[serde(default)]
+    description: String,
+    budget: f32,
+    #[serde(default)]
+    cells: Vec<SeedCell>,
+    #[serde(default)]
+    machines: Vec<PlacedMachine>,
+    #[serde(default)]
+    pipes: Vec<(i32, i32)>,
+    #[serde(default)]
+    lines: Vec<(i32, i32)>,
+    /// Impassable terrain, placed as walls.
+    #[serde(default)]
+    walls: Vec<(i32, i32)>,
+    /// Research nodes already done.
+    #[serde(default)]
+    research: Vec<String>,
+    objectives: Vec<Objective>,
+    /// Seconds to meet every objective, if limited.
+    #[serde(default)]
+    time_limit: Option<f32>,
+}
+
+#[derive(Debug)]
+pub(super) enum ScenarioLoadError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for ScenarioLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioLoadError::Io(e) => write!(f, "could not read scenario: {e}"),
+            ScenarioLoadError::Ron(e) => write!(f, "could not parse scenario: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioLoadError {}
+
+impl From<std::io::Error> for ScenarioLoadError {
+    fn from(e: std::io::Error) -> Self {
+        ScenarioLoadError::Io(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for ScenarioLoadError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        ScenarioLoadError::Ron(e)
+    }
+}
+
+#[derive(Default, TypePath)]
+pub(super) struct ScenarioLoader;
+
+impl AssetLoader for ScenarioLoader {
+    type Asset = Scenario;
+    type Settings = ();
+    type Error = ScenarioLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Scenario, ScenarioLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["scenario.ron"]
+    }
+}
+
+// -----------------------------
+// Resources + Messages
+// -----------------------------
+
+/// Every scenario, and the one waiting to start once its file has loaded.
+#[derive(Resource)]
+pub(super) struct Scenarios {
+    handles: Vec<Handle<Scenario>>,
+    pending: Option<usize>,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+struct ObjectiveState {
+    done: bool,
+    /// Seconds the objective's condition has held in a row.
+    held: f32,
+    /// Last measured value, for the readout.
+    value: f32,
+}
+
+/// The scenario being played. Saved with the farm so a loaded game picks it back up.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub(super) struct ScenarioRun {
+    index: usize,
+    elapsed: f32,
+    objectives: Vec<ObjectiveState>,
+    /// Set once the scenario is won (true) or lost (false).
+    outcome: Option<bool>,
+}
+
+#[derive(Message, Debug, Clone, Copy)]
+pub(super) struct ObjectiveCompleted {
+    pub index: usize,
+}
+
+#[derive(Message, Debug, Clone, Copy)]
+pub(super) struct ScenarioFinished {
+    pub won: bool,
+}
+
+#[derive(Component)]
+pub(super) struct ObjectiveReadout;
+
+#[derive(Component)]
+pub(super) struct ResultsScreen;
+
+// -----------------------------
+// Setup
+// -----------------------------
+
+pub(super) fn load_scenarios(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.insert_resource(Scenarios {
+        handles: SCENARIOS.iter().map(|path| assets.load(*path)).collect(),
+        pending: None,
+    });
+}
+
+/// A new game opens on the first scenario; a loaded one carries on with the scenario
+/// in its save, if any.
+pub(super) fn queue_first_scenario(start: Res<StartMode>, mut scenarios: ResMut<Scenarios>) {
+    scenarios.pending = (*start == StartMode::New).then_some(0);
+}
+
+pub(super) fn report_loading(
+    assets: Res<AssetServer>,
+    scenarios: Res<Scenarios>,
+    mut progress: ResMut<LoadingProgress>,
+) {
+    for (path, handle) in SCENARIOS.iter().zip(&scenarios.handles) {
+        progress.report_asset(path, &assets, handle);
+    }
+}
+
+pub(super) fn setup_readout(mut commands: Commands, panel: Res<HudPanel>) {
+    spawn_readout(&mut commands, &panel, ObjectiveReadout);
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+/// F2 starts the next scenario, wrapping around after the last.
+pub(super) fn next_scenario(
+    keys: Res<ButtonInput<KeyCode>>,
+    run: Option<Res<ScenarioRun>>,
+    mut scenarios: ResMut<Scenarios>,
+) {
+    if !keys.just_pressed(KeyCode::F2) {
+        return;
+    }
+    let next = run.map_or(0, |r| (r.index + 1) % scenarios.handles.len());
+    scenarios.pending = Some(next);
+}
+
+/// Sets up the pending scenario once its file has loaded: clears the field and
+/// everything built on it, then seeds the grid, budget, research and buildings.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn start_scenario(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut scenarios: ResMut<Scenarios>,
+    assets: Res<Assets<Scenario>>,
+    mut grid: ResMut<FieldGrid>,
+    mut funds: ResMut<Funds>,
+    mut research: ResMut<Research>,
+    mut contracts: ResMut<Contracts>,
+    existing: Query<Entity, Buildings>,
+    results: Query<Entity, With<ResultsScreen>>,
+) {
+    let Some(index) = scenarios.pending else {
+        return;
+    };
+    let Some(scenario) = assets.get(&scenarios.handles[index]) else {
+        return;
+    };
+    scenarios.pending = None;
+
+    for e in existing.iter().chain(&results) {
+        commands.entity(e).despawn();
+    }
+
+    grid.aether.fill(0.0);
+    grid.crystal.fill(0.0);
+    grid.purity.fill(0.0);
+    grid.pressure.fill(0.0);
+    for c in &scenario.cells {
+        if !grid.in_bounds(c.x, c.y) {
+            warn!("scenario cell ({}, {}) is off the field", c.x, c.y);
+            continue;
+        }
+        let i = grid.idx(c.x, c.y);
+        grid.aether[i] = c.aether;
+        grid.crystal[i] = c.crystal;
+        grid.purity[i] = c.purity;
+    }
+
+    funds.0 = scenario.budget;
+    *research = Research::with_done(&scenario.research);
+    *contracts = Contracts::default();
+
+    for &(x, y) in &scenario.walls {
+        drones::spawn_wall(&mut commands, &mut meshes, &mut materials, x, y);
+    }
+    for &(x, y) in &scenario.pipes {
+        pipes::spawn_pipe(&mut commands, &mut meshes, &mut materials, x, y);
+    }
+    for &(x, y) in &scenario.lines {
+        power::spawn_line(&mut commands, &mut meshes, &mut materials, x, y);
+    }
+    for m in &scenario.machines {
+        if machines::machine_def(m.kind).is_none() {
+            warn!("scenario places {:?}, which is not a machine", m.kind);
+            continue;
+        }
+        machines::spawn_machine(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            m.kind,
+            m.tier,
+            m.x,
+            m.y,
+        );
+    }
+
+    commands.insert_resource(ScenarioRun {
+        index,
+        elapsed: 0.0,
+        objectives: vec![ObjectiveState::default(); scenario.objectives.len()],
+        outcome: None,
+    });
+    info!("scenario: {}. {}", scenario.name, scenario.description);
+}
+
+// -----------------------------
+// Simulation (fixed tick)
+// -----------------------------
+
+/// Checks every objective each tick. Met objectives stay met; the scenario is won once
+/// all are, and lost if the time limit runs out first.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn evaluate_objectives(
+    time: Res<Time>,
+    grid: Res<FieldGrid>,
+    funds: Res<Funds>,
+    scenarios: Res<Scenarios>,
+    assets: Res<Assets<Scenario>>,
+    run: Option<ResMut<ScenarioRun>>,
+    inventories: Query<&Inventory>,
+    mut completed: MessageWriter<ObjectiveCompleted>,
+    mut finished: MessageWriter<ScenarioFinished>,
+) {
+    let Some(mut run) = run else {
+        return;
+    };
+    if run.outcome.is_some() {
+        return;
+    }
+    let Some(scenario) = assets.get(&scenarios.handles[run.index]) else {
+        return;
+    };
+    let dt = time.delta_secs();
+    run.elapsed += dt;
+
+    let stored: u32 = inventories
+        .iter()
+        .flat_map(|inv| inv.iter())
+        .filter(|(item, _)| Grade::parse(item).1 == CRYSTAL)
+        .map(|(_, n)| n)
+        .sum();
+    let crystal = grid.crystal.iter().sum::<f32>() + stored as f32;
+    let peak_aether = grid.aether.iter().copied().fold(0.0, f32::max);
+
+    for (index, (objective, state)) in scenario
+        .objectives
+        .iter()
+        .zip(&mut run.objectives)
+        .enumerate()
+    {
+        let met = match *objective {
+            Objective::ReachCrystal { amount } => {
+                state.value = crystal;
+                crystal >= amount
+            }
+            Objective::KeepAetherBelow { max, seconds } => {
+                state.held = if peak_aether < max {
+                    state.held + dt
+                } else {
+                    0.0
+                };
+                state.value = state.held;
+                state.held >= seconds
+            }
+            Objective::EarnCredits { amount } => {
+                state.value = funds.0;
+                funds.0 >= amount
+            }
+        };
+        if met && !state.done {
+            state.done = true;
+            completed.write(ObjectiveCompleted { index });
+        }
+    }
+
+    if run.objectives.iter().all(|o| o.done) {
+        run.outcome = Some(true);
+        finished.write(ScenarioFinished { won: true });
+    } else if scenario
+        .time_limit
+        .is_some_and(|limit| run.elapsed >= limit)
+    {
+        run.outcome = Some(false);
+        finished.write(ScenarioFinished { won: false });
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+fn clock(secs: f32) -> String {
+    let secs = secs.max(0.0) as u32;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+pub(super) fn update_readout(
+    scenarios: Res<Scenarios>,
+    assets: Res<Assets<Scenario>>,
+    run: Option<Res<ScenarioRun>>,
+    mut q: Query<&mut Text, With<ObjectiveReadout>>,
+) {
+    let Ok(mut text) = q.single_mut() else { return };
+    let Some(run) = run else {
+        return;
+    };
+    let Some(scenario) = assets.get(&scenarios.handles[run.index]) else {
+        return;
+    };
+
+    let mut lines = vec![match scenario.time_limit {
+        Some(limit) => format!("{} ({} left)", scenario.name, clock(limit - run.elapsed)),
+        None => scenario.name.clone(),
+    }];
+    for (objective, state) in scenario.objectives.iter().zip(&run.objectives) {
+        let progress = match *objective {
+            _ if state.done => "done".to_string(),
+            Objective::ReachCrystal { amount } => format!("{:.0}/{amount:.0}", state.value),
+            Objective::KeepAetherBelow { seconds, .. } => {
+                format!("{:.0}/{seconds:.0}s", state.value)
+            }
+            Objective::EarnCredits { amount } => format!("{:.0}/{amount:.0}", state.value),
+        };
+        lines.push(format!("  {objective}: {progress}"));
+    }
+
+    let line = lines.join("\n");
+    if text.0 != line {
+        text.0 = line;
+    }
+}
+
+pub(super) fn log_objectives(
+    scenarios: Res<Scenarios>,
+    assets: Res<Assets<Scenario>>,
+    run: Option<Res<ScenarioRun>>,
+    mut completed: MessageReader<ObjectiveCompleted>,
+) {
+    let objectives = run
+        .and_then(|r| assets.get(&scenarios.handles[r.index]))
+        .map(|s| s.objectives.as_slice())
+        .unwrap_or_default();
+    for c in completed.read() {
+        if let Some(objective) = objectives.get(c.index) {
+            info!("objective complete: {objective}");
+        }
+    }
+}
+
+/// Puts up the results over the field when a scenario is won or lost.
+pub(super) fn show_results(
+    mut commands: Commands,
+    scenarios: Res<Scenarios>,
+    assets: Res<Assets<Scenario>>,
+    run: Option<Res<ScenarioRun>>,
+    mut finished: MessageReader<ScenarioFinished>,
+) {
+    let Some(done) = finished.read().last() else {
+        return;
+    };
+    let Some(run) = run else {
+        return;
+    };
+    let Some(scenario) = assets.get(&scenarios.handles[run.index]) else {
+        return;
+    };
+
+    let (title, color) = if done.won {
+        ("Scenario complete", Color::srgb(0.5, 1.0, 0.6))
+    } else {
+        ("Scenario failed", Color::srgb(1.0, 0.45, 0.35))
+    };
+    let mut lines = vec![format!("{} in {}", scenario.name, clock(run.elapsed))];
+    for (objective, state) in scenario.objectives.iter().zip(&run.objectives) {
+        let mark = if state.done { "[x]" } else { "[ ]" };
+        lines.push(format!("{mark} {objective}"));
+    }
+    lines.push(String::new());
+    lines.push("F2 for the next scenario".to_string());
+
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        Pickable::IGNORE,
+        ResultsScreen,
+        children![(
+            Node {
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(10.0),
+                padding: UiRect::all(Val::Px(24.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.02, 0.02, 0.06, 0.92)),
+            children![
+                (
+                    Text::new(title),
+                    TextFont {
+                        font_size: 28.0,
+                        ..default()
+                    },
+                    TextColor(color),
+                ),
+                (
+                    Text::new(lines.join("\n")),
+                    TextFont {
+                        font_size: 16.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                ),
+            ],
+        )],
+        DespawnOnExit(InSession),
+    ));
+}
@@ -0,0 +1,771 @@
+﻿//! Tiny expression language run by controller blocks.
+//!
+//! ```text
+//! # keep the field around the controller in the sweet spot
+//! let a = aether(0, 1)
+//! if a < 3 { set(0, 1) } else if a > 7 { set(0, 0) }
+//! let n            # declared without a value: keeps it between ticks
+//! n = n + dt()
+//! ```
+//!
+//! Source compiles to bytecode for a small stack machine. Variables persist between
+//! ticks; every run is capped by an instruction budget so a loop can't stall the game.
+
+use std::fmt;
+
+// -----------------------------
+// Builtins
+// -----------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Builtin {
+    Aether,
+    Crystal,
+    Sensor,
+    Set,
+    Time,
+    Dt,
+    Min,
+    Max,
+    Abs,
+    Clamp,
+}
+
+impl Builtin {
+    fn lookup(name: &str) -> Option<(Builtin, usize)> {
+        Some(match name {
+            "aether" => (Builtin::Aether, 2),
+            "crystal" => (Builtin::Crystal, 2),
+            "sensor" => (Builtin::Sensor, 1),
+            "set" => (Builtin::Set, 2),
+            "time" => (Builtin::Time, 0),
+            "dt" => (Builtin::Dt, 0),
+            "min" => (Builtin::Min, 2),
+            "max" => (Builtin::Max, 2),
+            "abs" => (Builtin::Abs, 1),
+            "clamp" => (Builtin::Clamp, 3),
+            _ => return None,
+        })
+    }
+}
+
+/// What a running program can see and touch. Pure math builtins never reach the host.
+pub(super) trait Host {
+    fn call(&mut self, builtin: Builtin, args: &[f32]) -> f32;
+}
+
+// -----------------------------
+// Errors
+// -----------------------------
+
+#[derive(Clone, Debug)]
+pub(super) struct CompileError {
+    pub(super) line: usize,
+    pub(super) message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum RunError {
+    BudgetExhausted,
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::BudgetExhausted => write!(f, "instruction budget exhausted"),
+        }
+    }
+}
+
+// -----------------------------
+// Lexer
+// -----------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Num(f32),
+    Ident(String),
+    Let,
+    If,
+    Else,
+    While,
+    And,
+    Or,
+    Not,
+    Op(&'static str),
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+    Semi,
+    Eof,
+}
+
+fn lex(src: &str) -> Result<Vec<(Tok, usize)>, CompileError> {
+    let mut out = Vec::new();
+    let mut line = 1;
+    let mut chars = src.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_digit() || c == '.' {
+                        s.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let n = s.parse().map_err(|_| CompileError {
+                    line,
+                    message: format!("bad number `{s}`"),
+                })?;
+                out.push((Tok::Num(n), line));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        s.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let tok = match s.as_str() {
+                    "let" => Tok::Let,
+                    "if" => Tok::If,
+                    "else" => Tok::Else,
+                    "while" => Tok::While,
+                    "and" => Tok::And,
+                    "or" => Tok::Or,
+                    "not" => Tok::Not,
+                    _ => Tok::Ident(s),
+                };
+                out.push((tok, line));
+            }
+            _ => {
+                chars.next();
+                let next = chars.peek().copied();
+                let tok = match (c, next) {
+                    ('=', Some('=')) => Tok::Op("=="),
+                    ('!', Some('=')) => Tok::Op("!="),
+                    ('<', Some('=')) => Tok::Op("<="),
+                    ('>', Some('=')) => Tok::Op(">="),
+                    ('=', _) => Tok::Op("="),
+                    ('<', _) => Tok::Op("<"),
+                    ('>', _) => Tok::Op(">"),
+                    ('+', _) => Tok::Op("+"),
+                    ('-', _) => Tok::Op("-"),
+                    ('*', _) => Tok::Op("*"),
+                    ('/', _) => Tok::Op("/"),
+                    ('(', _) => Tok::LParen,
+                    (')', _) => Tok::RParen,
+                    ('{', _) => Tok::LBrace,
+                    ('}', _) => Tok::RBrace,
+                    (',', _) => Tok::Comma,
+                    (';', _) => Tok::Semi,
+                    _ => {
+                        return Err(CompileError {
+                            line,
+                            message: format!("unexpected `{c}`"),
+                        });
+                    }
+                };
+                if let Tok::Op(op) = tok
+                    && op.len() == 2
+                {
+                    chars.next();
+                }
+                out.push((tok, line));
+            }
+        }
+    }
+
+    out.push((Tok::Eof, line));
+    Ok(out)
+}
+
+// -----------------------------
+// Compiler
+// -----------------------------
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Push(f32),
+    Load(usize),
+    Store(usize),
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Not,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    Jump(usize),
+    JumpIfFalse(usize),
+    Call(Builtin, usize),
+}
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct Program {
+    code: Vec<Op>,
+    vars: Vec<String>,
+}
+
+impl Program {
+    pub(super) fn var_count(&self) -> usize {
+        self.vars.len()
+    }
+}
+
+// Deep enough for any sane program; guards the recursive compiler against pathological
+// nesting of expressions, unary operators and blocks.
+const MAX_DEPTH: usize = 64;
+
+struct Compiler {
+    toks: Vec<(Tok, usize)>,
+    pos: usize,
+    depth: usize,
+    program: Program,
+}
+
+pub(super) fn compile(src: &str) -> Result<Program, CompileError> {
+    let mut c = Compiler {
+        toks: lex(src)?,
+        pos: 0,
+        depth: 0,
+        program: Program::default(),
+    };
+    while c.peek() != &Tok::Eof {
+        c.statement()?;
+    }
+    Ok(c.program)
+}
+
+impl Compiler {
+    fn peek(&self) -> &Tok {
+        &self.toks[self.pos].0
+    }
+
+    fn line(&self) -> usize {
+        self.toks[self.pos].1
+    }
+
+    fn next(&mut self) -> Tok {
+        let t = self.toks[self.pos].0.clone();
+        if t != Tok::Eof {
+            self.pos += 1;
+        }
+        t
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, CompileError> {
+        Err(CompileError {
+            line: self.line(),
+            message: message.into(),
+        })
+    }
+
+    fn expect(&mut self, tok: Tok, what: &str) -> Result<(), CompileError> {
+        if *self.peek() == tok {
+            self.next();
+            Ok(())
+        } else {
+            self.error(format!("expected {what}"))
+        }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.program.code.push(op);
+        self.program.code.len() - 1
+    }
+
+    fn patch(&mut self, at: usize) {
+        let target = self.program.code.len();
+        match &mut self.program.code[at] {
+            Op::Jump(t) | Op::JumpIfFalse(t) => *t = target,
+            _ => unreachable!("only jumps are patched"),
+        }
+    }
+
+    fn var(&self, name: &str) -> Option<usize> {
+        self.program.vars.iter().position(|v| v == name)
+    }
+
+    fn statement(&mut self) -> Result<(), CompileError> {
+        match self.peek().clone() {
+            Tok::Let => {
+                self.next();
+                let Tok::Ident(name) = self.next() else {
+                    return self.error("expected a variable name after `let`");
+                };
+                if Builtin::lookup(&name).is_some() {
+                    return self.error(format!("`{name}` is a builtin"));
+                }
+                let slot = match self.var(&name) {
+                    Some(slot) => slot,
+                    None => {
+                        self.program.vars.push(name);
+                        self.program.vars.len() - 1
+                    }
+                };
+                if *self.peek() == Tok::Op("=") {
+                    self.next();
+                    self.expr()?;
+                    self.emit(Op::Store(slot));
+                }
+            }
+            Tok::If => self.if_statement()?,
+            Tok::While => {
+                self.next();
+                let top = self.program.code.len();
+                self.expr()?;
+                let exit = self.emit(Op::JumpIfFalse(0));
+                self.block()?;
+                self.emit(Op::Jump(top));
+                self.patch(exit);
+            }
+            Tok::Ident(name) if self.toks[self.pos + 1].0 == Tok::Op("=") => {
+                let Some(slot) = self.var(&name) else {
+                    return self
+                        .error(format!("unknown variable `{name}` (declare it with `let`)"));
+                };
+                self.next();
+                self.next();
+                self.expr()?;
+                self.emit(Op::Store(slot));
+            }
+            Tok::Semi => {
+                self.next();
+            }
+            _ => {
+                self.expr()?;
+                self.emit(Op::Pop);
+            }
+        }
+        Ok(())
+    }
+
+    fn if_statement(&mut self) -> Result<(), CompileError> {
+        self.expect(Tok::If, "`if`")?;
+        self.expr()?;
+        let skip = self.emit(Op::JumpIfFalse(0));
+        self.block()?;
+
+        if *self.peek() == Tok::Else {
+            self.next();
+            let end = self.emit(Op::Jump(0));
+            self.patch(skip);
+            if *self.peek() == Tok::If {
+                self.nested(Self::if_statement)?;
+            } else {
+                self.block()?;
+            }
+            self.patch(end);
+        } else {
+            self.patch(skip);
+        }
+        Ok(())
+    }
+
+    /// Runs one level of recursion, failing once the program nests past `MAX_DEPTH`.
+    fn nested(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<(), CompileError>,
+    ) -> Result<(), CompileError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return self.error("nested too deeply");
+        }
+        let r = f(self);
+        self.depth -= 1;
+        r
+    }
+
+    fn block(&mut self) -> Result<(), CompileError> {
+        self.expect(Tok::LBrace, "`{`")?;
+        self.nested(|c| {
+            while !matches!(c.peek(), Tok::RBrace | Tok::Eof) {
+                c.statement()?;
+            }
+            Ok(())
+        })?;
+        self.expect(Tok::RBrace, "`}`")
+    }
+
+    fn expr(&mut self) -> Result<(), CompileError> {
+        self.nested(Self::or)
+    }
+
+    fn or(&mut self) -> Result<(), CompileError> {
+        self.and()?;
+        while *self.peek() == Tok::Or {
+            self.next();
+            self.and()?;
+            self.emit(Op::Or);
+        }
+        Ok(())
+    }
+
+    fn and(&mut self) -> Result<(), CompileError> {
+        self.comparison()?;
+        while *self.peek() == Tok::And {
+            self.next();
+            self.comparison()?;
+            self.emit(Op::And);
+        }
+        Ok(())
+    }
+
+    fn comparison(&mut self) -> Result<(), CompileError> {
+        self.additive()?;
+        loop {
+            let op = match self.peek() {
+                Tok::Op("==") => Op::Eq,
+                Tok::Op("!=") => Op::Ne,
+                Tok::Op("<") => Op::Lt,
+                Tok::Op("<=") => Op::Le,
+                Tok::Op(">") => Op::Gt,
+                Tok::Op(">=") => Op::Ge,
+                _ => return Ok(()),
+            };
+            self.next();
+            self.additive()?;
+            self.emit(op);
+        }
+    }
+
+    fn additive(&mut self) -> Result<(), CompileError> {
+        self.term()?;
+        loop {
+            let op = match self.peek() {
+                Tok::Op("+") => Op::Add,
+                Tok::Op("-") => Op::Sub,
+                _ => return Ok(()),
+            };
+            self.next();
+            self.term()?;
+            self.emit(op);
+        }
+    }
+
+    fn term(&mut self) -> Result<(), CompileError> {
+        self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Tok::Op("*") => Op::Mul,
+                Tok::Op("/") => Op::Div,
+                _ => return Ok(()),
+            };
+            self.next();
+            self.unary()?;
+            self.emit(op);
+        }
+    }
+
+    fn unary(&mut self) -> Result<(), CompileError> {
+        match self.peek() {
+            Tok::Op("-") => {
+                self.next();
+                self.nested(Self::unary)?;
+                self.emit(Op::Neg);
+            }
+            Tok::Not => {
+                self.next();
+                self.nested(Self::unary)?;
+                self.emit(Op::Not);
+            }
+            _ => self.primary()?,
+        }
+        Ok(())
+    }
+
+    fn primary(&mut self) -> Result<(), CompileError> {
+        match self.next() {
+            Tok::Num(n) => {
+                self.emit(Op::Push(n));
+            }
+            Tok::LParen => {
+                self.expr()?;
+                self.expect(Tok::RParen, "`)`")?;
+            }
+            Tok::Ident(name) if *self.peek() == Tok::LParen => {
+                let Some((builtin, arity)) = Builtin::lookup(&name) else {
+                    return self.error(format!("unknown function `{name}`"));
+                };
+                self.next();
+                let mut argc = 0;
+                while *self.peek() != Tok::RParen {
+                    if argc > 0 {
+                        self.expect(Tok::Comma, "`,` between arguments")?;
+                    }
+                    self.expr()?;
+                    argc += 1;
+                }
+                self.next();
+                if argc != arity {
+                    return self.error(format!("`{name}` takes {arity} argument(s), got {argc}"));
+                }
+                self.emit(Op::Call(builtin, argc));
+            }
+            Tok::Ident(name) => {
+                let Some(slot) = self.var(&name) else {
+                    return self.error(format!("unknown variable `{name}`"));
+                };
+                self.emit(Op::Load(slot));
+            }
+            Tok::Eof => return self.error("unexpected end of program"),
+            t => return self.error(format!("unexpected {t:?}")),
+        }
+        Ok(())
+    }
+}
+
+// -----------------------------
+// Virtual machine
+// -----------------------------
+
+/// Runs `program` once from the top. `memory` holds its variables and must be at least
+/// `program.var_count()` long. Stops early once `budget` instructions have executed.
+pub(super) fn run(
+    program: &Program,
+    memory: &mut [f32],
+    host: &mut impl Host,
+    budget: u32,
+) -> Result<u32, RunError> {
+    let mut stack: Vec<f32> = Vec::with_capacity(16);
+    let mut pc = 0;
+    let mut used = 0;
+    let truth = |b: bool| b as u8 as f32;
+
+    while let Some(&op) = program.code.get(pc) {
+        used += 1;
+        if used > budget {
+            return Err(RunError::BudgetExhausted);
+        }
+        pc += 1;
+
+        match op {
+            Op::Push(n) => stack.push(n),
+            Op::Load(slot) => stack.push(memory[slot]),
+            Op::Store(slot) => memory[slot] = stack.pop().unwrap_or(0.0),
+            Op::Pop => {
+                stack.pop();
+            }
+            Op::Neg => {
+                let a = stack.pop().unwrap_or(0.0);
+                stack.push(-a);
+            }
+            Op::Not => {
+                let a = stack.pop().unwrap_or(0.0);
+                stack.push(truth(a == 0.0));
+            }
+            Op::Jump(t) => pc = t,
+            Op::JumpIfFalse(t) => {
+                if stack.pop().unwrap_or(0.0) == 0.0 {
+                    pc = t;
+                }
+            }
+            Op::Call(builtin, argc) => {
+                let args = stack.split_off(stack.len() - argc);
+                let v = match builtin {
+                    Builtin::Min => args[0].min(args[1]),
+                    Builtin::Max => args[0].max(args[1]),
+                    Builtin::Abs => args[0].abs(),
+                    Builtin::Clamp => args[0].clamp(args[1].min(args[2]), args[2].max(args[1])),
+                    _ => host.call(builtin, &args),
+                };
+                stack.push(if v.is_finite() { v } else { 0.0 });
+            }
+            binary => {
+                let b = stack.pop().unwrap_or(0.0);
+                let a = stack.pop().unwrap_or(0.0);
+                let v = match binary {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    // dividing by zero yields zero rather than poisoning the program with inf
+                    Op::Div if b == 0.0 => 0.0,
+                    Op::Div => a / b,
+                    Op::Eq => truth(a == b),
+                    Op::Ne => truth(a != b),
+                    Op::Lt => truth(a < b),
+                    Op::Le => truth(a <= b),
+                    Op::Gt => truth(a > b),
+                    Op::Ge => truth(a >= b),
+                    Op::And => truth(a != 0.0 && b != 0.0),
+                    Op::Or => truth(a != 0.0 || b != 0.0),
+                    _ => unreachable!("non-binary ops are handled above"),
+                };
+                stack.push(if v.is_finite() { v } else { 0.0 });
+            }
+        }
+    }
+
+    Ok(used)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records host calls; sensors read back their id.
+    #[derive(Default)]
+    struct TestHost {
+        sets: Vec<(f32, f32)>,
+    }
+
+    impl Host for TestHost {
+        fn call(&mut self, builtin: Builtin, args: &[f32]) -> f32 {
+            match builtin {
+                Builtin::Set => {
+                    self.sets.push((args[0], args[1]));
+                    0.0
+                }
+                Builtin::Sensor => args[0],
+                _ => 0.0,
+            }
+        }
+    }
+
+    fn run_src(src: &str, budget: u32) -> (Vec<f32>, TestHost, Result<u32, RunError>) {
+        let program = compile(src).expect("program compiles");
+        let mut memory = vec![0.0; program.var_count()];
+        let mut host = TestHost::default();
+        let result = run(&program, &mut memory, &mut host, budget);
+        (memory, host, result)
+    }
+
+    #[test]
+    fn lexes_keywords_numbers_and_operators() {
+        let toks: Vec<Tok> = lex("let a = 1.5 # comment\nif a >= 2 { }")
+            .unwrap()
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect();
+        assert_eq!(
+            toks,
+            vec![
+                Tok::Let,
+                Tok::Ident("a".into()),
+                Tok::Op("="),
+                Tok::Num(1.5),
+                Tok::If,
+                Tok::Ident("a".into()),
+                Tok::Op(">="),
+                Tok::Num(2.0),
+                Tok::LBrace,
+                Tok::RBrace,
+                Tok::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_tracks_lines_and_rejects_stray_characters() {
+        let toks = lex("a\n\nb").unwrap();
+        assert_eq!(toks[1], (Tok::Ident("b".into()), 3));
+
+        let err = lex("a\n$").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(lex("1.2.3").is_err());
+    }
+
+    #[test]
+    fn reports_compile_errors_with_their_line() {
+        let err = compile("let a = 1\nb = 2").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("unknown variable `b`"));
+
+        assert!(compile("set(1)").unwrap_err().message.contains("takes 2"));
+        assert!(
+            compile("nope(1)")
+                .unwrap_err()
+                .message
+                .contains("unknown function")
+        );
+        assert!(
+            compile("let min = 1")
+                .unwrap_err()
+                .message
+                .contains("builtin")
+        );
+        assert!(compile("if 1 { set(0, 1)").is_err());
+        assert!(compile("(1 + 2").is_err());
+    }
+
+    #[test]
+    fn runs_control_flow_and_builtins() {
+        let src = "
+            let n = 0
+            let total = 0
+            while n < 4 { n = n + 1  total = total + n }
+            if total > 100 { set(0, 0) } else if total == 10 { set(sensor(2), -total) }
+            let m = clamp(7, 0, 5) + min(1, 2) + abs(-1) / 0
+        ";
+        let (memory, host, result) = run_src(src, 1000);
+        assert!(result.is_ok());
+        assert_eq!(memory, vec![4.0, 10.0, 6.0]);
+        assert_eq!(host.sets, vec![(2.0, -10.0)]);
+    }
+
+    #[test]
+    fn stops_when_the_budget_runs_out() {
+        let (memory, _, result) = run_src("let n = 0 while 1 { n = n + 1 }", 200);
+        assert_eq!(result, Err(RunError::BudgetExhausted));
+        assert!(memory[0] > 0.0);
+
+        let (_, _, result) = run_src("let n = 1 + 2", 200);
+        assert_eq!(result, Ok(4));
+    }
+
+    #[test]
+    fn rejects_programs_nested_past_the_limit() {
+        let deep = MAX_DEPTH * 4;
+        let cases = [
+            format!("{}1{}", "(".repeat(deep), ")".repeat(deep)),
+            format!("{}1", "- ".repeat(deep)),
+            format!("{}1", "not ".repeat(deep)),
+            format!("{}{}", "if 1 { ".repeat(deep), "}".repeat(deep)),
+            format!("{}{}", "while 0 { ".repeat(deep), "}".repeat(deep)),
+            format!("if 0 {{ }}{}", " else if 0 { }".repeat(deep)),
+        ];
+        for src in cases {
+            let err = compile(&src).unwrap_err();
+            assert!(err.message.contains("nested too deeply"), "{src}: {err}");
+        }
+
+        // ordinary nesting stays well inside the limit
+        assert!(compile("if 1 { if 1 { while 0 { let a = -(-(not 1)) } } }").is_ok());
+    }
+}
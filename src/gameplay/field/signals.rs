@@ -0,0 +1,445 @@
+﻿use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::influence::{Influence, cardinal_step};
+use super::pipes::label_cells;
+use super::{
+    CursorCell, FieldGrid, Layout, MAX_AETHER, Machine, Occupancy, SelectedTool, Tool, cell_world,
+    world_cell,
+};
+use crate::state::InSession;
+
+// Crystal reading that counts as a full-scale (1.0) sensor signal.
+const CRYSTAL_FULL_SCALE: f32 = 12.0;
+// Signals above this count as "on" for logic and enable/inhibit receivers.
+const LOGIC_HIGH: f32 = 0.5;
+
+// -----------------------------
+// Components + Resources
+// -----------------------------
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum Reading {
+    #[default]
+    Aether,
+    Crystal,
+}
+
+/// Reads its own cell and drives every wire network next to it.
+#[derive(Component, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub(super) struct Sensor {
+    pub(super) reading: Reading,
+    #[serde(skip)]
+    output: f32,
+}
+
+impl Sensor {
+    pub(super) fn output(&self) -> f32 {
+        self.output
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum GateKind {
+    #[default]
+    And,
+    Or,
+    Not,
+    Threshold,
+    Timer,
+}
+
+/// Logic block. It outputs on the side its `Influence::facing` points to
+/// and reads every other wire network it touches.
+#[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
+pub(super) struct Gate {
+    pub(super) kind: GateKind,
+    pub(super) threshold: f32,
+    pub(super) period: f32,
+    #[serde(skip)]
+    elapsed: f32,
+    #[serde(skip)]
+    output: f32,
+}
+
+impl Default for Gate {
+    fn default() -> Self {
+        Self {
+            kind: GateKind::And,
+            threshold: 0.5,
+            period: 2.0,
+            elapsed: 0.0,
+            output: 0.0,
+        }
+    }
+}
+
+impl Gate {
+    pub(super) fn output(&self) -> f32 {
+        self.output
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum ReceiverMode {
+    /// Runs only while the signal is high.
+    #[default]
+    Enable,
+    /// Runs only while the signal is low.
+    Inhibit,
+    /// Output scales with the signal.
+    Modulate,
+}
+
+/// How a machine reacts to wire networks next to it. Unwired machines run freely.
+#[derive(Component, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub(super) struct Receiver {
+    pub(super) mode: ReceiverMode,
+    #[serde(skip)]
+    input: Option<f32>,
+}
+
+impl Receiver {
+    pub(super) fn factor(&self) -> f32 {
+        let Some(v) = self.input else { return 1.0 };
+        match self.mode {
+            ReceiverMode::Enable => (v > LOGIC_HIGH) as u8 as f32,
+            ReceiverMode::Inhibit => (v <= LOGIC_HIGH) as u8 as f32,
+            ReceiverMode::Modulate => v.clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[derive(Component)]
+pub(super) struct SignalWire {
+    x: i32,
+    y: i32,
+}
+
+impl SignalWire {
+    pub(super) fn cell(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+}
+
+#[derive(Resource, Default)]
+pub(super) struct SignalNetworks {
+    cells: HashMap<(i32, i32), usize>,
+    values: Vec<f32>,
+    /// Sensors and gates with the networks they drive.
+    outputs: Vec<(Entity, Vec<usize>)>,
+    /// Gates and receivers with the networks they read.
+    inputs: Vec<(Entity, Vec<usize>)>,
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+pub(super) fn place_wire(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    tool: Res<SelectedTool>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    occupancy: Occupancy,
+) {
+    if tool.0 != Tool::SignalWire || !keys.just_pressed(KeyCode::Space) {
+        return;
+    }
+    if !occupancy.is_free(cursor.x, cursor.y) {
+        return;
+    }
+
+    spawn_wire(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        cursor.x,
+        cursor.y,
+    );
+}
+
+pub(super) fn spawn_wire(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    x: i32,
+    y: i32,
+) -> Entity {
+    commands
+        .spawn((
+            Mesh3d(meshes.add(Cuboid::new(0.12, 0.08, 0.12))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::srgb(0.6, 0.3, 0.9),
+                ..default()
+            })),
+            Transform::from_translation(cell_world(x, y) + Vec3::Y * 0.14),
+            SignalWire { x, y },
+            DespawnOnExit(InSession),
+        ))
+        .id()
+}
+
+pub(super) fn remove_wire(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut commands: Commands,
+    wires: Query<(Entity, &SignalWire)>,
+) {
+    if !keys.just_pressed(KeyCode::KeyX) {
+        return;
+    }
+
+    for (e, w) in &wires {
+        if w.cell() == (cursor.x, cursor.y) {
+            commands.entity(e).despawn();
+        }
+    }
+}
+
+/// Configures the machine under the cursor: C cycles the sensor reading, gate kind or
+/// receiver mode; on gates - = adjust the threshold and [ ] the timer period.
+pub(super) fn configure_signals(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut q: Query<(
+        &Transform,
+        &mut Receiver,
+        Option<&mut Sensor>,
+        Option<&mut Gate>,
+    )>,
+) {
+    let pressed = [
+        KeyCode::KeyC,
+        KeyCode::Minus,
+        KeyCode::Equal,
+        KeyCode::BracketLeft,
+        KeyCode::BracketRight,
+    ];
+    if !keys.any_just_pressed(pressed) {
+        return;
+    }
+
+    for (t, mut receiver, sensor, gate) in &mut q {
+        if world_cell(t.translation) != (cursor.x, cursor.y) {
+            continue;
+        }
+
+        if let Some(mut sensor) = sensor {
+            if keys.just_pressed(KeyCode::KeyC) {
+                sensor.reading = match sensor.reading {
+                    Reading::Aether => Reading::Crystal,
+                    Reading::Crystal => Reading::Aether,
+                };
+                info!("sensor reads {:?}", sensor.reading);
+            }
+        } else if let Some(mut gate) = gate {
+            if keys.just_pressed(KeyCode::KeyC) {
+                gate.kind = match gate.kind {
+                    GateKind::And => GateKind::Or,
+                    GateKind::Or => GateKind::Not,
+                    GateKind::Not => GateKind::Threshold,
+                    GateKind::Threshold => GateKind::Timer,
+                    GateKind::Timer => GateKind::And,
+                };
+            }
+            if keys.just_pressed(KeyCode::Minus) {
+                gate.threshold = (gate.threshold - 0.1).clamp(0.0, 1.0);
+            }
+            if keys.just_pressed(KeyCode::Equal) {
+                gate.threshold = (gate.threshold + 0.1).clamp(0.0, 1.0);
+            }
+            if keys.just_pressed(KeyCode::BracketLeft) {
+                gate.period = (gate.period * 0.5).max(0.25);
+            }
+            if keys.just_pressed(KeyCode::BracketRight) {
+                gate.period = (gate.period * 2.0).min(64.0);
+            }
+            info!(
+                "gate {:?}: threshold {:.1}, period {:.2}s",
+                gate.kind, gate.threshold, gate.period
+            );
+        } else if keys.just_pressed(KeyCode::KeyC) {
+            receiver.mode = match receiver.mode {
+                ReceiverMode::Enable => ReceiverMode::Inhibit,
+                ReceiverMode::Inhibit => ReceiverMode::Modulate,
+                ReceiverMode::Modulate => ReceiverMode::Enable,
+            };
+            info!("machine signal mode {:?}", receiver.mode);
+        }
+    }
+}
+
+// -----------------------------
+// Network solving
+// -----------------------------
+
+#[allow(clippy::type_complexity)]
+pub(super) fn rebuild_signal_networks(
+    layout: Res<Layout>,
+    mut networks: ResMut<SignalNetworks>,
+    turned: Query<(), Changed<Influence>>,
+    wires: Query<&SignalWire>,
+    machines: Query<(Entity, &Transform, &Influence, Has<Sensor>, Has<Gate>), With<Machine>>,
+) {
+    // gates output on the side they face, so turning one rewires it too
+    if !layout.is_changed() && turned.is_empty() {
+        return;
+    }
+
+    let (cells, sizes) = label_cells(wires.iter().map(SignalWire::cell));
+    let mut outputs = Vec::new();
+    let mut inputs = Vec::new();
+
+    for (e, t, inf, is_sensor, is_gate) in &machines {
+        let (x, y) = world_cell(t.translation);
+        // gates output on the cardinal side they face
+        let ahead = cardinal_step(inf.facing, x, y);
+
+        let mut outs = Vec::new();
+        let mut ins = Vec::new();
+        for n in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+            let Some(&id) = cells.get(&n) else { continue };
+            let side = if is_sensor || (is_gate && n == ahead) {
+                &mut outs
+            } else {
+                &mut ins
+            };
+            if !side.contains(&id) {
+                side.push(id);
+            }
+        }
+
+        if !outs.is_empty() {
+            outputs.push((e, outs));
+        }
+        if !ins.is_empty() {
+            inputs.push((e, ins));
+        }
+    }
+
+    *networks = SignalNetworks {
+        cells,
+        values: vec![0.0; sizes.len()],
+        outputs,
+        inputs,
+    };
+}
+
+/// Runs after the field step: sensors sample the grid, gates evaluate on last tick's
+/// network values, then networks and receivers pick up the new outputs.
+///
+/// Every gate is evaluated, wired or not; a gate with no inputs reads them as low,
+/// so an unwired NOT outputs high and an unwired Timer free-runs.
+pub(super) fn evaluate_signals(
+    time: Res<Time>,
+    grid: Res<FieldGrid>,
+    mut networks: ResMut<SignalNetworks>,
+    mut sensors: Query<(&Transform, &mut Sensor)>,
+    mut gates: Query<(Entity, &mut Gate)>,
+    mut receivers: Query<&mut Receiver>,
+) {
+    let dt = time.delta_secs();
+
+    for (t, mut sensor) in &mut sensors {
+        let (x, y) = world_cell(t.translation);
+        if !grid.in_bounds(x, y) {
+            continue;
+        }
+        let i = grid.idx(x, y);
+        sensor.output = match sensor.reading {
+            Reading::Aether => grid.aether[i] / MAX_AETHER,
+            Reading::Crystal => grid.crystal[i] / CRYSTAL_FULL_SCALE,
+        }
+        .clamp(0.0, 1.0);
+    }
+
+    let net = &*networks;
+    for (e, mut gate) in &mut gates {
+        let values: Vec<f32> = net
+            .inputs
+            .iter()
+            .find(|(g, _)| *g == e)
+            .map(|(_, ins)| ins.iter().map(|&id| net.values[id]).collect())
+            .unwrap_or_default();
+        let high = |v: &f32| *v > LOGIC_HIGH;
+
+        let on = match gate.kind {
+            GateKind::And => !values.is_empty() && values.iter().all(high),
+            GateKind::Or => values.iter().any(high),
+            GateKind::Not => !values.iter().any(high),
+            GateKind::Threshold => values.iter().any(|&v| v >= gate.threshold),
+            GateKind::Timer => {
+                // free-runs while every input is high
+                if values.iter().all(high) {
+                    gate.elapsed += dt;
+                    if gate.elapsed >= gate.period {
+                        gate.elapsed = 0.0;
+                        gate.output = 1.0 - gate.output;
+                    }
+                } else {
+                    gate.elapsed = 0.0;
+                    gate.output = 0.0;
+                }
+                gate.output > LOGIC_HIGH
+            }
+        };
+        gate.output = on as u8 as f32;
+    }
+
+    let mut values = vec![0.0_f32; networks.values.len()];
+    for (e, outs) in &networks.outputs {
+        let out = sensors
+            .get(*e)
+            .map(|(_, s)| s.output)
+            .or_else(|_| gates.get(*e).map(|(_, g)| g.output))
+            .unwrap_or(0.0);
+        for &id in outs {
+            values[id] = values[id].max(out);
+        }
+    }
+    networks.values = values;
+
+    for mut receiver in &mut receivers {
+        receiver.input = None;
+    }
+    for (e, ins) in &networks.inputs {
+        if let Ok(mut receiver) = receivers.get_mut(*e) {
+            let v = ins
+                .iter()
+                .map(|&id| networks.values[id])
+                .fold(0.0, f32::max);
+            receiver.input = Some(v);
+        }
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+pub(super) fn update_wire_visuals(
+    networks: Res<SignalNetworks>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    wires: Query<(&SignalWire, &MeshMaterial3d<StandardMaterial>)>,
+) {
+    if !networks.is_changed() {
+        return;
+    }
+
+    for (w, mat) in &wires {
+        let v = networks
+            .cells
+            .get(&w.cell())
+            .and_then(|&id| networks.values.get(id))
+            .copied()
+            .unwrap_or(0.0);
+        if let Some(mat) = materials.get_mut(&mat.0) {
+            mat.emissive = LinearRgba::rgb(0.8, 0.3, 1.0) * (0.05 + 1.5 * v);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+﻿use std::time::Duration;
+
+use bevy::ecs::schedule::ScheduleLabel;
+use bevy::prelude::*;
+
+use super::hud::{HudPanel, spawn_readout};
+use super::{FieldFixedStep, FieldStep};
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+const MULTIPLIERS: &[u32] = &[1, 2, 4, 8];
+
+// -----------------------------
+// Resources + Components
+// -----------------------------
+
+/// How fast the farm runs. Faster speeds run more ticks per frame rather than longer
+/// ones, so the field stays as stable as at 1x.
+#[derive(Resource, Default)]
+pub(super) struct SimSpeed {
+    /// Index into `MULTIPLIERS`.
+    speed: usize,
+    paused: bool,
+    /// Single ticks asked for while paused, for each of the two steps.
+    step_field: bool,
+    step_fixed: bool,
+}
+
+impl SimSpeed {
+    fn multiplier(&self) -> u32 {
+        MULTIPLIERS[self.speed]
+    }
+}
+
+/// Clocks the farm's systems see in place of `Time`. They only advance while the farm
+/// runs, so pausing freezes waveforms and timers without touching the camera or UI.
+#[derive(Resource, Default)]
+pub(super) struct SimClock {
+    field: Time,
+    fixed: Time,
+}
+
+#[derive(Component)]
+pub(super) struct SpeedReadout;
+
+// -----------------------------
+// Setup
+// -----------------------------
+
+pub(super) fn setup_readout(mut commands: Commands, panel: Res<HudPanel>) {
+    spawn_readout(&mut commands, &panel, SpeedReadout);
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+/// F6 pauses, F7 steps one tick while paused, F8 cycles 1x/2x/4x/8x.
+pub(super) fn speed_input(keys: Res<ButtonInput<KeyCode>>, mut speed: ResMut<SimSpeed>) {
+    if keys.just_pressed(KeyCode::F6) {
+        speed.paused = !speed.paused;
+    }
+    if keys.just_pressed(KeyCode::F7) {
+        if speed.paused {
+            speed.step_field = true;
+            speed.step_fixed = true;
+        } else {
+            info!("pause with F6 to step one tick at a time");
+        }
+    }
+    if keys.just_pressed(KeyCode::F8) {
+        speed.speed = (speed.speed + 1) % MULTIPLIERS.len();
+    }
+}
+
+// -----------------------------
+// Simulation
+// -----------------------------
+
+/// Runs `schedule` `ticks` times, each `dt` long on the farm's own clock.
+fn run_ticks(
+    world: &mut World,
+    schedule: impl ScheduleLabel + Clone,
+    clock: fn(&mut SimClock) -> &mut Time,
+    ticks: u32,
+    dt: Duration,
+) {
+    if ticks == 0 {
+        return;
+    }
+    let real = *world.resource::<Time>();
+    let mut sim = *clock(&mut world.resource_mut::<SimClock>());
+    for _ in 0..ticks {
+        sim.advance_by(dt);
+        *world.resource_mut::<Time>() = sim;
+        world.run_schedule(schedule.clone());
+    }
+    *clock(&mut world.resource_mut::<SimClock>()) = sim;
+    *world.resource_mut::<Time>() = real;
+}
+
+/// Ticks of a step to run now, taking a pending single step if paused.
+fn ticks(speed: &mut SimSpeed, step: fn(&mut SimSpeed) -> &mut bool) -> u32 {
+    if !speed.paused {
+        return speed.multiplier();
+    }
+    u32::from(std::mem::take(step(speed)))
+}
+
+/// The field's per-frame step, at the chosen speed.
+pub(super) fn run_field_step(world: &mut World) {
+    let ticks = ticks(&mut world.resource_mut::<SimSpeed>(), |s| &mut s.step_field);
+    let dt = world.resource::<Time>().delta();
+    run_ticks(world, FieldStep, |c| &mut c.field, ticks, dt);
+}
+
+/// Drones, logistics and the economy, once per fixed tick at the chosen speed.
+pub(super) fn run_fixed_step(world: &mut World) {
+    let ticks = ticks(&mut world.resource_mut::<SimSpeed>(), |s| &mut s.step_fixed);
+    let dt = world.resource::<Time>().delta();
+    run_ticks(world, FieldFixedStep, |c| &mut c.fixed, ticks, dt);
+}
+
+/// Runs `ticks` ticks of both steps, interleaved, regardless of speed; used to catch up.
+pub(super) fn fast_forward(world: &mut World, ticks: u32, dt: Duration) {
+    for _ in 0..ticks {
+        run_ticks(world, FieldStep, |c| &mut c.field, 1, dt);
+        run_ticks(world, FieldFixedStep, |c| &mut c.fixed, 1, dt);
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+pub(super) fn update_readout(speed: Res<SimSpeed>, mut q: Query<&mut Text, With<SpeedReadout>>) {
+    let Ok(mut text) = q.single_mut() else { return };
+
+    let line = if speed.paused {
+        "Paused (F6 resume, F7 step)".to_string()
+    } else {
+        format!("Speed: {}x (F6 pause, F8 change)", speed.multiplier())
+    };
+    if text.0 != line {
+        text.0 = line;
+    }
+}
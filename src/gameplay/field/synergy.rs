@@ -0,0 +1,157 @@
+﻿use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::machines::machine_def;
+use super::pipes::Pipe;
+use super::power::PowerLine;
+use super::signals::SignalWire;
+use super::{CursorCell, Layout, Machine, SelectedTool, Tool, cell_world, world_cell};
+
+// -----------------------------
+// Definitions
+// -----------------------------
+
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Bonus {
+    /// Extra cells of reach.
+    Range(i32),
+    /// Added fraction of strength, e.g. 0.15 for +15%.
+    Strength(f32),
+    /// Widens a stabilizer's sweet spot by this much aether at both ends.
+    SweetSpot(f32),
+}
+
+/// A bonus a machine gets for each cardinal neighbour of kind `with`.
+/// Conduits count as neighbours too, so `with` can be a pipe or wire.
+pub(super) struct Synergy {
+    pub(super) with: Tool,
+    pub(super) bonus: Bonus,
+}
+
+// -----------------------------
+// Components + Resources
+// -----------------------------
+
+/// Bonuses a machine currently gets from its neighbours. Recomputed whenever
+/// something is built or removed.
+#[derive(Component, Clone, Copy, Debug)]
+pub(super) struct Synergies {
+    range: i32,
+    strength: f32,
+    pub(super) sweet_spot: f32,
+}
+
+impl Default for Synergies {
+    fn default() -> Self {
+        Self {
+            range: 0,
+            strength: 1.0,
+            sweet_spot: 0.0,
+        }
+    }
+}
+
+impl Synergies {
+    pub(super) fn radius(&self, m: &Machine) -> i32 {
+        m.radius + self.range
+    }
+
+    pub(super) fn strength(&self, m: &Machine) -> f32 {
+        m.strength * self.strength
+    }
+}
+
+/// What occupies each cell, by kind, for neighbour lookups.
+#[derive(Resource, Default)]
+pub(super) struct Neighborhood {
+    kinds: HashMap<(i32, i32), Tool>,
+}
+
+impl Neighborhood {
+    fn around(&self, x: i32, y: i32) -> impl Iterator<Item = ((i32, i32), Tool)> + '_ {
+        [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+            .into_iter()
+            .filter_map(|c| self.kinds.get(&c).map(|&k| (c, k)))
+    }
+}
+
+fn synergizes(kind: Tool, with: Tool) -> bool {
+    machine_def(kind).is_some_and(|d| d.synergies.iter().any(|s| s.with == with))
+}
+
+// -----------------------------
+// Simulation
+// -----------------------------
+
+pub(super) fn rebuild_synergies(
+    layout: Res<Layout>,
+    mut neighborhood: ResMut<Neighborhood>,
+    mut machines: Query<(&Transform, &Machine, &mut Synergies)>,
+    pipes: Query<&Pipe>,
+    lines: Query<&PowerLine>,
+    wires: Query<&SignalWire>,
+) {
+    if !layout.is_changed() {
+        return;
+    }
+
+    let mut kinds: HashMap<(i32, i32), Tool> = machines
+        .iter()
+        .map(|(t, m, _)| (world_cell(t.translation), m.kind))
+        .collect();
+    kinds.extend(pipes.iter().map(|p| (p.cell(), Tool::Pipe)));
+    kinds.extend(lines.iter().map(|l| (l.cell(), Tool::PowerLine)));
+    kinds.extend(wires.iter().map(|w| (w.cell(), Tool::SignalWire)));
+    neighborhood.kinds = kinds;
+
+    for (t, m, mut syn) in &mut machines {
+        let Some(def) = machine_def(m.kind) else {
+            continue;
+        };
+        let (x, y) = world_cell(t.translation);
+
+        let mut next = Synergies::default();
+        for (_, kind) in neighborhood.around(x, y) {
+            for s in def.synergies.iter().filter(|s| s.with == kind) {
+                match s.bonus {
+                    Bonus::Range(r) => next.range += r,
+                    Bonus::Strength(f) => next.strength += f,
+                    Bonus::SweetSpot(a) => next.sweet_spot += a,
+                }
+            }
+        }
+        *syn = next;
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+/// Links the cursor to every neighbour the selected tool would form a synergy with,
+/// in either direction.
+pub(super) fn draw_synergy_preview(
+    mut gizmos: Gizmos,
+    cursor: Res<CursorCell>,
+    tool: Res<SelectedTool>,
+    neighborhood: Res<Neighborhood>,
+) {
+    if neighborhood.kinds.contains_key(&(cursor.x, cursor.y)) {
+        return;
+    }
+
+    let from = cell_world(cursor.x, cursor.y) + Vec3::Y * 0.9;
+    for ((x, y), kind) in neighborhood.around(cursor.x, cursor.y) {
+        if !synergizes(tool.0, kind) && !synergizes(kind, tool.0) {
+            continue;
+        }
+        let to = cell_world(x, y) + Vec3::Y * 0.9;
+        gizmos.line(from, to, Color::srgb(1.0, 0.85, 0.2));
+        gizmos.sphere(
+            Isometry3d::from_translation(to),
+            0.12,
+            Color::srgb(1.0, 0.85, 0.2),
+        );
+    }
+}
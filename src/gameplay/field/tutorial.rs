@@ -0,0 +1,241 @@
+﻿use bevy::prelude::*;
+
+use super::contracts::ContractsText;
+use super::economy::{Funds, FundsReadout};
+use super::power::{Powered, power_draw};
+use super::research::ResearchBrowser;
+use super::scenario::ObjectiveReadout;
+use super::{CursorCell, FieldGrid, Machine, Tool, ToolReadout};
+use crate::ui::tutorial::{Highlighted, TutorialPrompt};
+
+// -----------------------------
+// Steps
+// -----------------------------
+
+/// What a step waits for before the tutorial moves on.
+enum Wait {
+    /// The cursor left the cell it was on when the step began.
+    CursorMoved,
+    MachinePlaced(Tool),
+    /// Every machine that draws power is getting enough to run.
+    AllPowered,
+    CrystalAbove(f32),
+    /// Credits went up since the step began.
+    CreditsGained,
+    ResearchOpened,
+    Seconds(f32),
+}
+
+/// HUD element a step points at.
+#[derive(Clone, Copy)]
+enum Highlight {
+    Tool,
+    Funds,
+    Objectives,
+    Contracts,
+}
+
+struct Step {
+    prompt: &'static str,
+    highlight: Option<Highlight>,
+    wait: Wait,
+}
+
+static STEPS: &[Step] = &[
+    Step {
+        prompt: "Welcome to the crystal farm. Move the yellow cursor with the arrow keys.",
+        highlight: None,
+        wait: Wait::CursorMoved,
+    },
+    Step {
+        prompt: "Press 1 to pick the Emitter, then Space to place it on an empty cell. \
+                 Emitters pump aether into the cells around them.",
+        highlight: Some(Highlight::Tool),
+        wait: Wait::MachinePlaced(Tool::Emitter),
+    },
+    Step {
+        prompt: "Press 3 for a Stabilizer and place it near the aether. It grows crystal \
+                 wherever the aether sits between 3 and 7.5.",
+        highlight: Some(Highlight::Tool),
+        wait: Wait::MachinePlaced(Tool::Stabilizer),
+    },
+    Step {
+        prompt: "Machines need power. Press 5 for a Generator and place it close by.",
+        highlight: Some(Highlight::Tool),
+        wait: Wait::MachinePlaced(Tool::Generator),
+    },
+    Step {
+        prompt: "Press 7 for Power Lines and lay a line that touches the generator, the \
+                 emitter and the stabilizer.",
+        highlight: Some(Highlight::Tool),
+        wait: Wait::AllPowered,
+    },
+    Step {
+        prompt: "Give the crystal time to grow. Press 2 for a Sink if a cell runs too hot.",
+        highlight: Some(Highlight::Objectives),
+        wait: Wait::CrystalAbove(10.0),
+    },
+    Step {
+        prompt: "Move the cursor onto a crystal cell and press S to sell what grew there.",
+        highlight: Some(Highlight::Funds),
+        wait: Wait::CreditsGained,
+    },
+    Step {
+        prompt: "Press T to browse research. More machines unlock as you research them.",
+        highlight: None,
+        wait: Wait::ResearchOpened,
+    },
+    Step {
+        prompt: "Buyers post contracts down here; press J to accept one. F5 saves and F9 \
+                 loads. Good luck!",
+        highlight: Some(Highlight::Contracts),
+        wait: Wait::Seconds(10.0),
+    },
+];
+
+// -----------------------------
+// Resources
+// -----------------------------
+
+/// Progress through the tutorial; removed when it ends or is skipped.
+#[derive(Resource, Default)]
+pub(super) struct Tutorial {
+    step: usize,
+    /// Whether the current step's prompt and highlight are shown yet.
+    entered: bool,
+    elapsed: f32,
+    credits_at_start: f32,
+    cursor_at: (i32, i32),
+}
+
+// -----------------------------
+// Setup
+// -----------------------------
+
+/// Every session starts the tutorial from the top.
+pub(super) fn start_tutorial(mut commands: Commands) {
+    commands.insert_resource(Tutorial::default());
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+/// F1 skips the tutorial, or starts it over once it is gone.
+pub(super) fn toggle_tutorial(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut commands: Commands,
+    tutorial: Option<Res<Tutorial>>,
+    mut prompt: ResMut<TutorialPrompt>,
+    highlighted: Query<Entity, With<Highlighted>>,
+) {
+    if !keys.just_pressed(KeyCode::F1) {
+        return;
+    }
+    if tutorial.is_some() {
+        end(&mut commands, &mut prompt, &highlighted);
+        info!("tutorial skipped; F1 starts it again");
+    } else {
+        commands.init_resource::<Tutorial>();
+    }
+}
+
+fn end(
+    commands: &mut Commands,
+    prompt: &mut TutorialPrompt,
+    highlighted: &Query<Entity, With<Highlighted>>,
+) {
+    commands.remove_resource::<Tutorial>();
+    prompt.0 = None;
+    for e in highlighted {
+        commands.entity(e).remove::<Highlighted>();
+    }
+}
+
+// -----------------------------
+// Progress
+// -----------------------------
+
+/// Shows the current step and moves on once what it waits for has happened.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(super) fn run_tutorial(
+    time: Res<Time>,
+    mut commands: Commands,
+    tutorial: Option<ResMut<Tutorial>>,
+    mut prompt: ResMut<TutorialPrompt>,
+    cursor: Res<CursorCell>,
+    grid: Res<FieldGrid>,
+    funds: Res<Funds>,
+    browser: Option<Res<ResearchBrowser>>,
+    placed: Query<&Machine, Added<Machine>>,
+    powered: Query<(&Machine, &Powered)>,
+    highlighted: Query<Entity, With<Highlighted>>,
+    targets: Query<(
+        Entity,
+        Has<ToolReadout>,
+        Has<FundsReadout>,
+        Has<ObjectiveReadout>,
+        Has<ContractsText>,
+    )>,
+) {
+    let Some(mut tutorial) = tutorial else {
+        return;
+    };
+    let Some(step) = STEPS.get(tutorial.step) else {
+        end(&mut commands, &mut prompt, &highlighted);
+        info!("tutorial complete");
+        return;
+    };
+
+    if !tutorial.entered {
+        tutorial.entered = true;
+        tutorial.elapsed = 0.0;
+        tutorial.credits_at_start = funds.0;
+        tutorial.cursor_at = (cursor.x, cursor.y);
+        prompt.0 = Some(format!(
+            "{} ({}/{}, F1 to skip)",
+            step.prompt,
+            tutorial.step + 1,
+            STEPS.len()
+        ));
+        for e in &highlighted {
+            commands.entity(e).remove::<Highlighted>();
+        }
+        if let Some(highlight) = step.highlight {
+            for (e, tool, funds, objectives, contracts) in &targets {
+                let hit = match highlight {
+                    Highlight::Tool => tool,
+                    Highlight::Funds => funds,
+                    Highlight::Objectives => objectives,
+                    Highlight::Contracts => contracts,
+                };
+                if hit {
+                    commands.entity(e).insert(Highlighted);
+                }
+            }
+        }
+        // a step can't be finished by what happened before it was shown
+        return;
+    }
+    tutorial.elapsed += time.delta_secs();
+
+    let done = match step.wait {
+        Wait::CursorMoved => (cursor.x, cursor.y) != tutorial.cursor_at,
+        Wait::MachinePlaced(kind) => placed.iter().any(|m| m.kind == kind),
+        Wait::AllPowered => {
+            let mut drawing = powered
+                .iter()
+                .filter(|(m, _)| power_draw(m) > 0.0)
+                .peekable();
+            drawing.peek().is_some() && drawing.all(|(_, p)| p.factor() > 0.0)
+        }
+        Wait::CrystalAbove(amount) => grid.crystal.iter().sum::<f32>() > amount,
+        Wait::CreditsGained => funds.0 > tutorial.credits_at_start,
+        Wait::ResearchOpened => browser.is_some(),
+        Wait::Seconds(secs) => tutorial.elapsed >= secs,
+    };
+    if done {
+        tutorial.step += 1;
+        tutorial.entered = false;
+    }
+}
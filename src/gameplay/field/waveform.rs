@@ -0,0 +1,199 @@
+﻿use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::machines::{machine_def, tier_emissive};
+use super::{CursorCell, Machine, world_cell};
+
+const MIN_PERIOD: f32 = 0.25;
+const MAX_PERIOD: f32 = 32.0;
+
+// -----------------------------
+// Components
+// -----------------------------
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum Program {
+    #[default]
+    Constant,
+    Square,
+    Sine,
+    Ramp,
+    /// Fires for `duty * period` seconds each time it is triggered.
+    Burst,
+}
+
+impl Program {
+    fn next(self) -> Self {
+        match self {
+            Program::Constant => Program::Square,
+            Program::Square => Program::Sine,
+            Program::Sine => Program::Ramp,
+            Program::Ramp => Program::Burst,
+            Program::Burst => Program::Constant,
+        }
+    }
+}
+
+/// Drives an emitter's output over time. `phase` is a fraction of the period,
+/// so two emitters with the same period can be set to interfere on purpose.
+#[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
+pub(super) struct Waveform {
+    pub(super) program: Program,
+    pub(super) period: f32,
+    pub(super) duty: f32,
+    pub(super) phase: f32,
+    /// Sim time the last burst started, taken by [`sample_waveforms`] once one is pending.
+    #[serde(skip)]
+    triggered_at: Option<f32>,
+    #[serde(skip)]
+    burst_pending: bool,
+    #[serde(skip)]
+    level: f32,
+}
+
+impl Default for Waveform {
+    fn default() -> Self {
+        Self {
+            program: Program::Constant,
+            period: 4.0,
+            duty: 0.5,
+            phase: 0.0,
+            triggered_at: None,
+            burst_pending: false,
+            level: 1.0,
+        }
+    }
+}
+
+impl Waveform {
+    /// Output multiplier in 0..1 at time `t` seconds.
+    pub(super) fn sample(&self, t: f32) -> f32 {
+        let cycle = (t / self.period + self.phase).rem_euclid(1.0);
+        match self.program {
+            Program::Constant => 1.0,
+            Program::Square => {
+                if cycle < self.duty {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Program::Sine => 0.5 - 0.5 * (cycle * TAU).cos(),
+            Program::Ramp => cycle,
+            Program::Burst => match self.triggered_at {
+                Some(start) if t - start < self.duty * self.period => 1.0,
+                _ => 0.0,
+            },
+        }
+    }
+
+    /// Starts a burst on the next field tick, timed by the sim clock.
+    pub(super) fn trigger(&mut self) {
+        self.burst_pending = true;
+    }
+
+    /// Last sampled output, for systems that run after [`sample_waveforms`].
+    pub(super) fn level(&self) -> f32 {
+        self.level
+    }
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+/// Edits the program of the emitter under the cursor:
+/// P cycles programs, [ ] halve/double the period, - = adjust duty, , . shift phase, B fires a burst.
+pub(super) fn edit_waveform(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut q: Query<(&Transform, &mut Waveform)>,
+) {
+    let pressed = [
+        KeyCode::KeyP,
+        KeyCode::BracketLeft,
+        KeyCode::BracketRight,
+        KeyCode::Minus,
+        KeyCode::Equal,
+        KeyCode::Comma,
+        KeyCode::Period,
+        KeyCode::KeyB,
+    ];
+    if !keys.any_just_pressed(pressed) {
+        return;
+    }
+
+    for (t, mut wave) in &mut q {
+        if world_cell(t.translation) != (cursor.x, cursor.y) {
+            continue;
+        }
+
+        if keys.just_pressed(KeyCode::KeyP) {
+            wave.program = wave.program.next();
+        }
+        if keys.just_pressed(KeyCode::BracketLeft) {
+            wave.period = (wave.period * 0.5).max(MIN_PERIOD);
+        }
+        if keys.just_pressed(KeyCode::BracketRight) {
+            wave.period = (wave.period * 2.0).min(MAX_PERIOD);
+        }
+        if keys.just_pressed(KeyCode::Minus) {
+            wave.duty = (wave.duty - 0.1).clamp(0.1, 0.9);
+        }
+        if keys.just_pressed(KeyCode::Equal) {
+            wave.duty = (wave.duty + 0.1).clamp(0.1, 0.9);
+        }
+        if keys.just_pressed(KeyCode::Comma) {
+            wave.phase = (wave.phase - 0.125).rem_euclid(1.0);
+        }
+        if keys.just_pressed(KeyCode::Period) {
+            wave.phase = (wave.phase + 0.125).rem_euclid(1.0);
+        }
+        if keys.just_pressed(KeyCode::KeyB) {
+            wave.trigger();
+        }
+
+        info!(
+            "emitter program {:?}: period {:.2}s, duty {:.0}%, phase {:.0}%",
+            wave.program,
+            wave.period,
+            wave.duty * 100.0,
+            wave.phase * 100.0
+        );
+    }
+}
+
+// -----------------------------
+// Simulation
+// -----------------------------
+
+pub(super) fn sample_waveforms(time: Res<Time>, mut q: Query<&mut Waveform>) {
+    let t = time.elapsed_secs();
+    for mut wave in &mut q {
+        if wave.burst_pending {
+            wave.burst_pending = false;
+            wave.triggered_at = Some(t);
+        }
+        wave.level = wave.sample(t);
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+pub(super) fn pulse_emitter_visuals(
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    q: Query<(&Machine, &Waveform, &MeshMaterial3d<StandardMaterial>)>,
+) {
+    for (m, wave, mat) in &q {
+        let Some(def) = machine_def(m.kind) else {
+            continue;
+        };
+        if let Some(mat) = materials.get_mut(&mat.0) {
+            mat.emissive = tier_emissive(def.color, m.tier) * (0.3 + 0.7 * wave.level);
+        }
+    }
+}
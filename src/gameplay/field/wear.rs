@@ -0,0 +1,174 @@
+﻿use bevy::prelude::*;
+
+use super::machines::{machine_def, tier_emissive};
+use super::overload::Disabled;
+use super::power::Output;
+use super::{CursorCell, Drive, FieldGrid, MAX_AETHER, Machine, Tool, world_cell};
+
+// -----------------------------
+// Tunables
+// -----------------------------
+
+// Wear in percent per second at full drive, and at full aether on the machine's cell.
+const WEAR_PER_LOAD: f32 = 0.4;
+const WEAR_PER_AETHER: f32 = 0.3;
+
+const BREAKDOWN_AT: f32 = 100.0;
+// Efficiency lost just before breaking down; it falls off linearly with wear.
+const MAX_WEAR_PENALTY: f32 = 0.4;
+
+// Repairs by hand are paid in crystal from the cells around the machine.
+const REPAIR_COST_PER_WEAR: f32 = 0.2;
+const REPAIR_RADIUS: i32 = 2;
+
+// -----------------------------
+// Components
+// -----------------------------
+
+/// Accumulated wear in percent. Machines run worse as it grows and stop at 100%.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub(super) struct Wear(pub(super) f32);
+
+impl Wear {
+    pub(super) fn is_broken(&self) -> bool {
+        self.0 >= BREAKDOWN_AT
+    }
+
+    /// Multiplier on output: 1 when new, `1 - MAX_WEAR_PENALTY` when worn out, 0 when broken.
+    pub(super) fn factor(&self) -> f32 {
+        if self.is_broken() {
+            0.0
+        } else {
+            1.0 - MAX_WEAR_PENALTY * self.0 / BREAKDOWN_AT
+        }
+    }
+}
+
+// -----------------------------
+// Input
+// -----------------------------
+
+/// M repairs the machine under the cursor completely, paid for in crystal.
+pub(super) fn repair_machine(
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<CursorCell>,
+    mut grid: ResMut<FieldGrid>,
+    mut q: Query<(&Transform, &Machine, &mut Wear)>,
+) {
+    if !keys.just_pressed(KeyCode::KeyM) {
+        return;
+    }
+
+    for (t, m, mut wear) in &mut q {
+        if world_cell(t.translation) != (cursor.x, cursor.y) || wear.0 <= 0.0 {
+            continue;
+        }
+        let name = machine_def(m.kind).map_or("machine", |d| d.name);
+        let cost = wear.0 * REPAIR_COST_PER_WEAR;
+        if !grid.take_crystal_around(cursor.x, cursor.y, m.radius.max(REPAIR_RADIUS), cost) {
+            info!("repairing {name} needs {cost:.0} crystal nearby");
+            continue;
+        }
+        wear.0 = 0.0;
+        info!("repaired {name}");
+    }
+}
+
+// -----------------------------
+// Simulation
+// -----------------------------
+
+/// Machines wear with how hard they are driven and how much aether they sit in.
+/// Generators take no drive from the grid, so they wear with their output instead.
+pub(super) fn accumulate_wear(
+    time: Res<Time>,
+    grid: Res<FieldGrid>,
+    mut q: Query<(&Transform, &Machine, &Drive, &Output, &mut Wear), Without<Disabled>>,
+) {
+    let dt = time.delta_secs();
+
+    for (t, m, drive, output, mut wear) in &mut q {
+        if wear.is_broken() {
+            continue;
+        }
+        let (x, y) = world_cell(t.translation);
+        let exposure = if grid.in_bounds(x, y) {
+            grid.aether[grid.idx(x, y)] / MAX_AETHER
+        } else {
+            0.0
+        };
+
+        let load = if m.kind == Tool::Generator {
+            output.0
+        } else {
+            drive.0
+        };
+        wear.0 =
+            (wear.0 + (WEAR_PER_LOAD * load + WEAR_PER_AETHER * exposure) * dt).min(BREAKDOWN_AT);
+        if wear.is_broken() {
+            let name = machine_def(m.kind).map_or("machine", |d| d.name);
+            warn!("{name} at ({x}, {y}) broke down; press M on it to repair");
+        }
+    }
+}
+
+/// Maintenance bays work off the wear of every other machine within their radius,
+/// including broken ones.
+pub(super) fn maintain_machines(
+    time: Res<Time>,
+    bays: Query<(Entity, &Transform, &Machine, &Drive), Without<Disabled>>,
+    mut q: Query<(Entity, &Transform, &mut Wear)>,
+) {
+    let dt = time.delta_secs();
+
+    for (bay, bt, m, drive) in &bays {
+        if m.kind != Tool::MaintenanceBay {
+            continue;
+        }
+        let Ok((_, _, bay_wear)) = q.get(bay) else {
+            continue;
+        };
+        if bay_wear.is_broken() {
+            continue;
+        }
+
+        let (bx, by) = world_cell(bt.translation);
+        let r = m.radius;
+        for (e, t, mut wear) in &mut q {
+            let (x, y) = world_cell(t.translation);
+            if e == bay || (x - bx).pow(2) + (y - by).pow(2) > r * r {
+                continue;
+            }
+            wear.0 = (wear.0 - m.strength * drive.0 * dt).max(0.0);
+        }
+    }
+}
+
+// -----------------------------
+// Visualization
+// -----------------------------
+
+/// Darkens worn machines and blacks out broken ones.
+pub(super) fn update_wear_visuals(
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    q: Query<(&Machine, &Wear, &MeshMaterial3d<StandardMaterial>), Changed<Wear>>,
+) {
+    for (m, wear, mat) in &q {
+        let Some(def) = machine_def(m.kind) else {
+            continue;
+        };
+        let Some(mat) = materials.get_mut(&mat.0) else {
+            continue;
+        };
+        let worn = Color::srgb(0.25, 0.2, 0.15);
+        if wear.is_broken() {
+            mat.base_color = Color::srgb(0.1, 0.1, 0.1);
+            mat.emissive = LinearRgba::BLACK;
+        } else {
+            mat.base_color = def.color.mix(&worn, 0.6 * wear.0 / BREAKDOWN_AT);
+            if m.kind != Tool::Emitter {
+                mat.emissive = tier_emissive(def.color, m.tier) * wear.factor();
+            }
+        }
+    }
+}
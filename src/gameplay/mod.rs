@@ -11,7 +11,8 @@ impl Plugin for GameplayPlugin {
         app.add_plugins((
             MeshPickingPlugin,
             scene::ScenePlugin,
-            shaders::ShaderLibraryPlugin
+            shaders::ShaderLibraryPlugin,
+            field::FieldTestPlugin,
         ));
     }
 }
\ No newline at end of file
@@ -5,6 +5,8 @@ pub struct CubePlugin;
 
 use bevy::picking::hover::Hovered;
 
+use crate::state::InSession;
+
 #[derive(Component)]
 struct Cube;
 
@@ -30,8 +32,8 @@ struct HoverLerp {
 impl Plugin for CubePlugin {
     fn build(&self, app: &mut App) {
         app.add_message::<SpawnCube>()
-           .add_systems(Update, handle_spawn_cube)
-           .add_systems(Update, lerp_hover_color);
+           .add_systems(Update, handle_spawn_cube.run_if(in_state(InSession)))
+           .add_systems(Update, lerp_hover_color.run_if(in_state(InSession)));
     }
 }
 
@@ -62,7 +64,8 @@ fn handle_spawn_cube(
             Hovered(false),
             HoverTint { normal, hover }, 
             HoverLerp { t: 0.0, speed: 4.0 },
-            crate::ui::tooltip::HoverTooltip(req.tooltip),
+            crate::ui::tooltip::HoverTooltip(req.tooltip.into()),
+            DespawnOnExit(InSession),
         ));
     }
 }
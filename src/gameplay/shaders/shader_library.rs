@@ -1,5 +1,7 @@
 ﻿use bevy::prelude::*;
 
+use crate::state::{AssetStatus, LoadingChecks, LoadingProgress};
+
 #[derive(Resource)]
 #[allow(dead_code)]
 pub struct ShaderLibrary {
@@ -19,6 +21,7 @@ impl Plugin for ShaderLibraryPlugin {
     fn build(&self, app: &mut App) {
         app.configure_sets(Startup, ShaderInitSet::Init);
         app.add_systems(Startup, init_materials.in_set(ShaderInitSet::Init));
+        app.add_systems(Update, report_materials.in_set(LoadingChecks));
     }
 }
 
@@ -57,3 +60,33 @@ fn init_materials(
         metal,
     });
 }
+
+/// Holds loading until the library exists and every template in it is still in
+/// `Assets`, so the field can take `Res<ShaderLibrary>` and its templates as given.
+fn report_materials(
+    library: Option<Res<ShaderLibrary>>,
+    materials: Res<Assets<StandardMaterial>>,
+    mut progress: ResMut<LoadingProgress>,
+) {
+    let status = match library {
+        None => AssetStatus::Pending,
+        Some(library) => {
+            let missing: Vec<&str> = [
+                ("crystal", &library.crystal),
+                ("crystal_hover", &library.crystal_hover),
+                ("metal", &library.metal),
+            ]
+            .into_iter()
+            .filter(|(_, handle)| !materials.contains(*handle))
+            .map(|(name, _)| name)
+            .collect();
+
+            if missing.is_empty() {
+                AssetStatus::Ready
+            } else {
+                AssetStatus::Failed(format!("missing {}", missing.join(", ")))
+            }
+        }
+    };
+    progress.report("materials", status);
+}
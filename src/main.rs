@@ -1,11 +1,15 @@
 mod ui;
 mod gameplay;
+mod settings;
+mod state;
 
 use bevy::prelude::*;
 use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
 
 use ui::UIPlugin;
 use gameplay::GameplayPlugin;
+use settings::{Settings, SettingsPlugin};
+use state::{GameState, InSession, StatePlugin};
 
 
 // MAIN RUST / BEVY TECH
@@ -29,13 +33,9 @@ use gameplay::GameplayPlugin;
 // “Only react when value changes”	Changed<T>
 // “One-time setup”	Startup system
 
-#[derive(Resource)]
-struct StartupDelay(Timer);
-
 fn main() {
     App::new()
         // .add_plugins(DefaultPlugins)
-        // .add_plugins(crate::gameplay::field::FieldTestPlugin)
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 visible: false,
@@ -44,37 +44,17 @@ fn main() {
             ..default()
         }))
         .add_plugins(PanOrbitCameraPlugin)
+        .add_plugins(StatePlugin)
+        .add_plugins(SettingsPlugin)
         .add_plugins(UIPlugin)
         .add_plugins(GameplayPlugin)
-        .add_systems(Startup, spawn_cubes.after(crate::gameplay::shaders::shader_library::ShaderInitSet::Init))
-        .add_systems(Startup, init_delay)
-        .add_systems(Startup, setup)
-        .add_systems(Update, show_window_after_delay)
+        .add_systems(
+            OnTransition { exited: GameState::Loading, entered: GameState::InGame },
+            (setup, spawn_cubes),
+        )
         .run();
 }
 
-fn init_delay(mut commands: Commands) {
-    commands.insert_resource(StartupDelay(
-        Timer::from_seconds(0.01, TimerMode::Once),
-    ));
-}
-
-fn show_window_after_delay(
-    time: Res<Time>,
-    mut delay: ResMut<StartupDelay>,
-    mut windows: Query<&mut Window>,
-) {
-    delay.0.tick(time.delta());
-
-    if delay.0.is_finished() {
-        if let Ok(mut window) = windows.single_mut() {
-            if !window.visible {
-                window.visible = true;
-            }
-        }
-    }
-}
-
 fn spawn_cubes(
     mut spawn_cube: MessageWriter<crate::gameplay::scene::shapes::SpawnCube>,
     mut materials: ResMut<Assets<StandardMaterial>>,
@@ -102,14 +82,21 @@ fn spawn_cubes(
     //     }
     // }
 
-    let unique_handle1 = instanced_material_from_template(&mut materials, &library.crystal);
+    // loading waits on the library's materials check, so a miss here is only logged
+    let Some(unique_handle1) = instanced_material_from_template(&mut materials, &library.crystal) else {
+        error!("crystal material missing; not spawning cubes");
+        return;
+    };
     spawn_cube.write(crate::gameplay::scene::shapes::SpawnCube {
         transform: Transform::from_xyz(0.0, 0.5002, 0.0),
         tooltip: "Cube 1",
         mat: unique_handle1,
     });
 
-    let unique_handle2 = instanced_material_from_template(&mut materials, &library.crystal);
+    let Some(unique_handle2) = instanced_material_from_template(&mut materials, &library.crystal) else {
+        error!("crystal material missing; not spawning cubes");
+        return;
+    };
     spawn_cube.write(crate::gameplay::scene::shapes::SpawnCube {
       transform: Transform::from_xyz(1.2, 0.5002, 1.2),
       tooltip: "Cube 2",
@@ -117,36 +104,37 @@ fn spawn_cubes(
     });
 }
 
+/// Copy of `template` as a new material, or `None` if the template isn't loaded.
 fn instanced_material_from_template(
     materials: &mut Assets<StandardMaterial>,
     template: &Handle<StandardMaterial>,
-) -> Handle<StandardMaterial> {
-    let mat = materials
-        .get(template)
-        .expect("Template material not found in Assets<StandardMaterial>")
-        .clone();
-    materials.add(mat)
+) -> Option<Handle<StandardMaterial>> {
+    let mat = materials.get(template)?.clone();
+    Some(materials.add(mat))
 }
 
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    settings: Res<Settings>,
 ) {
     // circle
     commands.spawn((
         Mesh3d(meshes.add(Circle::new(300.0))),
         MeshMaterial3d(materials.add(Color::WHITE)),
         Transform::from_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2)),
+        DespawnOnExit(InSession),
     ));
     
     // Light
     commands.spawn((
         PointLight {
-            shadows_enabled: true,
+            shadows_enabled: settings.shadows,
             ..default()
         },
         Transform::from_xyz(4.0, 8.0, 4.0),
+        DespawnOnExit(InSession),
     ));
 
     // Camera + Pan/Orbit controller
@@ -154,6 +142,7 @@ fn setup(
         Camera3d::default(),
         Transform::from_xyz(0.0, 8.0, 8.0).looking_at(Vec3::ZERO, Vec3::Y),
         PanOrbitCamera::default(),
+        DespawnOnExit(InSession),
     ));
     
 //     let mesh = meshes.add(crystal_mesh(7, 0.35, 1.2, 0.55));
@@ -0,0 +1,274 @@
+﻿use std::fs;
+use std::path::PathBuf;
+
+use bevy::audio::{GlobalVolume, Volume};
+use bevy::light::{DirectionalLightShadowMap, PointLightShadowMap};
+use bevy::prelude::*;
+use bevy::window::PresentMode;
+use bevy_panorbit_camera::PanOrbitCamera;
+use serde::{Deserialize, Serialize};
+
+const APP_DIR: &str = "crystalfarm";
+const SETTINGS_FILE: &str = "settings.ron";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GraphicsQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl GraphicsQuality {
+    const ALL: [Self; 3] = [Self::Low, Self::Medium, Self::High];
+
+    // 4x is the only multisample count every adapter supports, so High differs from
+    // Medium in its shadows alone
+    fn msaa(self) -> Msaa {
+        match self {
+            Self::Low => Msaa::Off,
+            Self::Medium | Self::High => Msaa::Sample4,
+        }
+    }
+
+    fn shadow_map_size(self) -> usize {
+        match self {
+            Self::Low => 512,
+            Self::Medium => 1024,
+            Self::High => 2048,
+        }
+    }
+}
+
+/// User preferences, kept in `settings.ron` in the user's config directory.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub quality: GraphicsQuality,
+    pub shadows: bool,
+    pub vsync: bool,
+    pub ui_scale: f32,
+    pub master_volume: f32,
+    pub camera_sensitivity: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            quality: GraphicsQuality::Medium,
+            shadows: true,
+            vsync: true,
+            ui_scale: 1.0,
+            master_volume: 1.0,
+            camera_sensitivity: 1.0,
+        }
+    }
+}
+
+/// One adjustable line of the settings screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    Quality,
+    Shadows,
+    Vsync,
+    UiScale,
+    MasterVolume,
+    CameraSensitivity,
+}
+
+impl Setting {
+    pub const ALL: [Self; 6] = [
+        Self::Quality,
+        Self::Shadows,
+        Self::Vsync,
+        Self::UiScale,
+        Self::MasterVolume,
+        Self::CameraSensitivity,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Quality => "Graphics quality",
+            Self::Shadows => "Shadows",
+            Self::Vsync => "VSync",
+            Self::UiScale => "UI scale",
+            Self::MasterVolume => "Master volume",
+            Self::CameraSensitivity => "Camera sensitivity",
+        }
+    }
+}
+
+fn on_off(on: bool) -> String {
+    if on { "On" } else { "Off" }.to_string()
+}
+
+fn percent(v: f32) -> String {
+    format!("{:.0}%", v * 100.0)
+}
+
+/// Moves `v` by `step` in `dir`, kept within `range` and rounded to the step.
+fn nudge(v: f32, dir: i8, step: f32, range: (f32, f32)) -> f32 {
+    let next = ((v / step).round() + f32::from(dir)) * step;
+    next.clamp(range.0, range.1)
+}
+
+impl Settings {
+    pub fn value(&self, setting: Setting) -> String {
+        match setting {
+            Setting::Quality => format!("{:?}", self.quality),
+            Setting::Shadows => on_off(self.shadows),
+            Setting::Vsync => on_off(self.vsync),
+            Setting::UiScale => percent(self.ui_scale),
+            Setting::MasterVolume => percent(self.master_volume),
+            Setting::CameraSensitivity => format!("{:.1}x", self.camera_sensitivity),
+        }
+    }
+
+    /// Steps `setting` down (`dir` < 0) or up; toggles flip either way.
+    pub fn adjust(&mut self, setting: Setting, dir: i8) {
+        match setting {
+            Setting::Quality => {
+                let all = GraphicsQuality::ALL;
+                let i = all.iter().position(|q| *q == self.quality).unwrap_or(1);
+                let i = i.saturating_add_signed(isize::from(dir)).min(all.len() - 1);
+                self.quality = all[i];
+            }
+            Setting::Shadows => self.shadows = !self.shadows,
+            Setting::Vsync => self.vsync = !self.vsync,
+            Setting::UiScale => self.ui_scale = nudge(self.ui_scale, dir, 0.1, (0.5, 2.0)),
+            Setting::MasterVolume => {
+                self.master_volume = nudge(self.master_volume, dir, 0.1, (0.0, 1.0));
+            }
+            Setting::CameraSensitivity => {
+                self.camera_sensitivity = nudge(self.camera_sensitivity, dir, 0.1, (0.1, 3.0));
+            }
+        }
+    }
+}
+
+/// `<config dir>/crystalfarm`, where settings and saves live, or `None` if the
+/// platform gives no home.
+pub(crate) fn app_dir() -> Option<PathBuf> {
+    let env = |name| std::env::var_os(name).map(PathBuf::from);
+    let base = if cfg!(target_os = "windows") {
+        env("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        env("XDG_CONFIG_HOME").or_else(|| env("HOME").map(|home| home.join(".config")))
+    };
+    Some(base?.join(APP_DIR))
+}
+
+/// `<config dir>/crystalfarm/settings.ron`.
+fn settings_path() -> Option<PathBuf> {
+    Some(app_dir()?.join(SETTINGS_FILE))
+}
+
+/// Reads the settings file, falling back to defaults if it is missing or unreadable.
+fn load_settings() -> Settings {
+    let Some(path) = settings_path() else {
+        return Settings::default();
+    };
+    let Ok(text) = fs::read_to_string(&path) else {
+        return Settings::default();
+    };
+    match ron::from_str(&text) {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!("ignoring {}: {e}", path.display());
+            Settings::default()
+        }
+    }
+}
+
+fn save_settings(settings: &Settings) {
+    let Some(path) = settings_path() else {
+        warn!("no config directory; settings are not saved");
+        return;
+    };
+    let text = match ron::ser::to_string_pretty(settings, ron::ser::PrettyConfig::default()) {
+        Ok(text) => text,
+        Err(e) => {
+            error!("failed to serialize settings: {e}");
+            return;
+        }
+    };
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, text));
+    if let Err(e) = written {
+        error!("failed to write {}: {e}", path.display());
+    }
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_settings())
+           .add_systems(Update, (apply_settings, save_changed_settings));
+    }
+}
+
+/// Pushes the settings into the engine whenever they change, and onto cameras and
+/// lights as they are spawned.
+#[allow(clippy::too_many_arguments)]
+fn apply_settings(
+    settings: Res<Settings>,
+    mut ui_scale: ResMut<UiScale>,
+    mut volume: ResMut<GlobalVolume>,
+    mut point_shadows: ResMut<PointLightShadowMap>,
+    mut directional_shadows: ResMut<DirectionalLightShadowMap>,
+    mut windows: Query<&mut Window>,
+    mut cameras: Query<&mut Msaa>,
+    mut orbits: Query<&mut PanOrbitCamera>,
+    mut point_lights: Query<&mut PointLight>,
+    mut directional_lights: Query<&mut DirectionalLight>,
+) {
+    let all = settings.is_changed();
+    if all {
+        ui_scale.0 = settings.ui_scale;
+        volume.volume = Volume::Linear(settings.master_volume);
+        point_shadows.size = settings.quality.shadow_map_size();
+        directional_shadows.size = settings.quality.shadow_map_size() * 2;
+        let present_mode = if settings.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+        for mut window in &mut windows {
+            window.present_mode = present_mode;
+        }
+    }
+
+    for mut msaa in &mut cameras {
+        if all || msaa.is_added() {
+            *msaa = settings.quality.msaa();
+        }
+    }
+    for mut orbit in &mut orbits {
+        if all || orbit.is_added() {
+            orbit.orbit_sensitivity = settings.camera_sensitivity;
+            orbit.pan_sensitivity = settings.camera_sensitivity;
+            orbit.zoom_sensitivity = settings.camera_sensitivity;
+        }
+    }
+    for mut light in &mut point_lights {
+        if all || light.is_added() {
+            light.shadows_enabled = settings.shadows;
+        }
+    }
+    for mut light in &mut directional_lights {
+        if all || light.is_added() {
+            light.shadows_enabled = settings.shadows;
+        }
+    }
+}
+
+/// Writes the settings file after every change made in the game.
+fn save_changed_settings(settings: Res<Settings>) {
+    if settings.is_changed() && !settings.is_added() {
+        save_settings(&settings);
+    }
+}
@@ -0,0 +1,167 @@
+﻿use bevy::asset::{RecursiveDependencyLoadState, UntypedAssetId};
+use bevy::prelude::*;
+
+use crate::gameplay::shaders::shader_library::ShaderLibrary;
+
+/// Top-level flow of the app: Boot -> MainMenu -> Loading -> InGame <-> Paused.
+#[derive(States, Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameState {
+    /// Window hidden while shared resources are created.
+    #[default]
+    Boot,
+    MainMenu,
+    /// Waiting on the assets a game needs; the world is spawned once they are in.
+    Loading,
+    InGame,
+    Paused,
+}
+
+/// Exists while a game is loading, running or paused. The world is spawned with
+/// `DespawnOnExit(InSession)` so going back to the main menu tears it down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InSession;
+
+impl ComputedStates for InSession {
+    type SourceStates = GameState;
+
+    fn compute(state: GameState) -> Option<Self> {
+        matches!(
+            state,
+            GameState::Loading | GameState::InGame | GameState::Paused
+        )
+        .then_some(InSession)
+    }
+}
+
+/// How the main menu asked for the session to start.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StartMode {
+    New,
+    Load,
+}
+
+/// Where one required asset (or group of them) stands.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetStatus {
+    Ready,
+    Pending,
+    Failed(String),
+}
+
+/// What the `LoadingChecks` systems reported this frame, by label.
+#[derive(Resource, Default)]
+pub struct LoadingProgress {
+    pub items: Vec<(&'static str, AssetStatus)>,
+}
+
+impl LoadingProgress {
+    pub fn report(&mut self, label: &'static str, status: AssetStatus) {
+        self.items.push((label, status));
+    }
+
+    /// Reports an asset requested from the asset server.
+    pub fn report_asset(
+        &mut self,
+        label: &'static str,
+        server: &AssetServer,
+        id: impl Into<UntypedAssetId>,
+    ) {
+        let status = match server.get_recursive_dependency_load_state(id) {
+            Some(RecursiveDependencyLoadState::Loaded) => AssetStatus::Ready,
+            Some(RecursiveDependencyLoadState::Failed(e)) => AssetStatus::Failed(e.to_string()),
+            Some(_) => AssetStatus::Pending,
+            None => AssetStatus::Failed("never requested".to_string()),
+        };
+        self.report(label, status);
+    }
+
+    pub fn ready(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|(_, s)| *s == AssetStatus::Ready)
+            .count()
+    }
+
+    pub fn failed(&self) -> bool {
+        self.items
+            .iter()
+            .any(|(_, s)| matches!(s, AssetStatus::Failed(_)))
+    }
+
+    fn done(&self) -> bool {
+        self.ready() == self.items.len()
+    }
+}
+
+/// Systems that report into `LoadingProgress`; they run every frame of `Loading`.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LoadingChecks;
+
+pub struct StatePlugin;
+
+impl Plugin for StatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<GameState>()
+           .add_computed_state::<InSession>()
+           .insert_resource(StartMode::New)
+           .init_resource::<LoadingProgress>()
+           .configure_sets(Update, LoadingChecks.run_if(in_state(GameState::Loading)))
+           .add_systems(Update, finish_boot.run_if(in_state(GameState::Boot)))
+           .add_systems(
+               Update,
+               (
+                   clear_progress.before(LoadingChecks),
+                   finish_loading.after(LoadingChecks),
+               )
+                   .run_if(in_state(GameState::Loading)),
+           )
+           .add_systems(
+               Update,
+               toggle_pause.run_if(in_state(GameState::InGame).or(in_state(GameState::Paused))),
+           );
+    }
+}
+
+/// Shows the window and opens the main menu once the shared materials exist.
+fn finish_boot(
+    library: Option<Res<ShaderLibrary>>,
+    mut windows: Query<&mut Window>,
+    mut next: ResMut<NextState<GameState>>,
+) {
+    if library.is_none() {
+        return;
+    }
+    if let Ok(mut window) = windows.single_mut() {
+        window.visible = true;
+    }
+    next.set(GameState::MainMenu);
+}
+
+fn clear_progress(mut progress: ResMut<LoadingProgress>) {
+    progress.items.clear();
+}
+
+/// Starts the game once everything reported is ready. A failure keeps the loading
+/// screen up with the error instead.
+fn finish_loading(progress: Res<LoadingProgress>, mut next: ResMut<NextState<GameState>>) {
+    if progress.done() {
+        next.set(GameState::InGame);
+    }
+}
+
+/// Escape pauses and resumes. Screens that close on Escape run before this and clear
+/// the press, so closing them doesn't pause the game too.
+pub fn toggle_pause(
+    keys: Res<ButtonInput<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut next: ResMut<NextState<GameState>>,
+) {
+    if !keys.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    match state.get() {
+        GameState::InGame => next.set(GameState::Paused),
+        GameState::Paused => next.set(GameState::InGame),
+        _ => {}
+    }
+}
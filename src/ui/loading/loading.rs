@@ -0,0 +1,132 @@
+﻿use bevy::prelude::*;
+
+use crate::state::{AssetStatus, GameState, LoadingChecks, LoadingProgress};
+use crate::ui::menu::{MenuButton, add_button};
+
+#[derive(Resource)]
+pub struct LoadingUi {
+    pub bar: Entity,
+    pub text: Entity,
+    pub back: Entity,
+}
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Loading), spawn_loading_screen)
+           .add_systems(
+               Update,
+               show_progress.after(LoadingChecks).run_if(in_state(GameState::Loading)),
+           );
+    }
+}
+
+pub fn spawn_loading_screen(mut commands: Commands) {
+    let scope = DespawnOnExit(GameState::Loading);
+    commands.spawn((Camera2d, scope.clone()));
+
+    let mut bar = Entity::PLACEHOLDER;
+    let mut text = Entity::PLACEHOLDER;
+
+    let root = commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.01, 0.02, 0.05)),
+            scope,
+        ))
+        .with_children(|root| {
+            root.spawn((
+                Text::new("Loading"),
+                TextFont {
+                    font_size: 32.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.6, 0.85, 1.0)),
+            ));
+
+            // track with the fill as a child, sized by the share of ready assets
+            root.spawn((
+                Node {
+                    width: Val::Px(320.0),
+                    height: Val::Px(10.0),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.1)),
+            ))
+            .with_children(|track| {
+                bar = track
+                    .spawn((
+                        Node {
+                            width: Val::Percent(0.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        BackgroundColor(Color::srgb(0.35, 0.75, 1.0)),
+                    ))
+                    .id();
+            });
+
+            text = root
+                .spawn((
+                    Text::new(""),
+                    TextFont {
+                        font_size: 14.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                ))
+                .id();
+        })
+        .id();
+
+    // only offered once something has failed
+    let back = add_button(&mut commands, root, "Main Menu", MenuButton::ToMainMenu);
+    commands.entity(back).insert(Visibility::Hidden);
+
+    commands.insert_resource(LoadingUi { bar, text, back });
+}
+
+pub fn show_progress(
+    progress: Res<LoadingProgress>,
+    ui: Res<LoadingUi>,
+    mut nodes: Query<&mut Node>,
+    mut texts: Query<(&mut Text, &mut TextColor)>,
+    mut vis: Query<&mut Visibility>,
+) {
+    let total = progress.items.len().max(1);
+    if let Ok(mut node) = nodes.get_mut(ui.bar) {
+        node.width = Val::Percent(progress.ready() as f32 / total as f32 * 100.0);
+    }
+
+    let failed = progress.failed();
+    if let Ok((mut text, mut color)) = texts.get_mut(ui.text) {
+        let lines: Vec<String> = progress
+            .items
+            .iter()
+            .map(|(label, status)| match status {
+                AssetStatus::Ready => format!("{label}: ready"),
+                AssetStatus::Pending => format!("{label}: loading"),
+                AssetStatus::Failed(e) => format!("{label}: FAILED - {e}"),
+            })
+            .collect();
+        let line = lines.join("\n");
+        if text.0 != line {
+            text.0 = line;
+        }
+        color.0 = if failed { Color::srgb(1.0, 0.45, 0.35) } else { Color::WHITE };
+    }
+
+    if failed && let Ok(mut v) = vis.get_mut(ui.back) {
+        *v = Visibility::Inherited;
+    }
+}
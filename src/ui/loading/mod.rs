@@ -0,0 +1,2 @@
+﻿mod loading;
+pub use loading::*;
\ No newline at end of file
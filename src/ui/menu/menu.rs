@@ -0,0 +1,271 @@
+﻿use bevy::prelude::*;
+
+use crate::settings::{Setting, Settings};
+use crate::state::{GameState, StartMode};
+
+/// Page of the main menu being shown.
+#[derive(SubStates, Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[source(GameState = GameState::MainMenu)]
+pub enum MenuScreen {
+    #[default]
+    Main,
+    Settings,
+}
+
+#[derive(Component, Clone, Copy)]
+pub enum MenuButton {
+    New,
+    Load,
+    Settings,
+    Quit,
+    Back,
+    Resume,
+    ToMainMenu,
+    /// Steps a setting down (-1) or up (1).
+    Adjust(Setting, i8),
+}
+
+/// Text showing the current value of a setting.
+#[derive(Component)]
+pub struct SettingValue(pub Setting);
+
+const BUTTON_NORMAL: Color = Color::srgba(0.10, 0.14, 0.24, 0.95);
+const BUTTON_HOVER: Color = Color::srgba(0.18, 0.26, 0.42, 0.95);
+const BUTTON_PRESSED: Color = Color::srgba(0.35, 0.55, 0.85, 0.95);
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_sub_state::<MenuScreen>()
+           .add_systems(OnEnter(GameState::MainMenu), spawn_menu_camera)
+           .add_systems(OnEnter(MenuScreen::Main), spawn_main_menu)
+           .add_systems(OnEnter(MenuScreen::Settings), spawn_settings_menu)
+           .add_systems(OnEnter(GameState::Paused), spawn_pause_menu)
+           .add_systems(Update, (tint_buttons, press_buttons, show_setting_values));
+    }
+}
+
+// the game camera only exists in a session, so the menu brings its own
+pub fn spawn_menu_camera(mut commands: Commands) {
+    commands.spawn((Camera2d, DespawnOnExit(GameState::MainMenu)));
+}
+
+/// Full-screen centred column, dimming whatever is behind it.
+fn menu_root(commands: &mut Commands, scope: impl Component, title: &str) -> Entity {
+    let root = commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.01, 0.02, 0.05, 0.85)),
+            scope,
+        ))
+        .id();
+
+    let heading = commands
+        .spawn((
+            Text::new(title),
+            TextFont {
+                font_size: 40.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.6, 0.85, 1.0)),
+            Node {
+                margin: UiRect::bottom(Val::Px(16.0)),
+                ..default()
+            },
+        ))
+        .id();
+    commands.entity(root).add_child(heading);
+    root
+}
+
+pub fn add_button(
+    commands: &mut Commands,
+    root: Entity,
+    label: &str,
+    action: MenuButton,
+) -> Entity {
+    let button = commands
+        .spawn((
+            Button,
+            action,
+            Node {
+                width: Val::Px(240.0),
+                padding: UiRect::all(Val::Px(10.0)),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(BUTTON_NORMAL),
+            children![(
+                Text::new(label),
+                TextFont {
+                    font_size: 20.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            )],
+        ))
+        .id();
+    commands.entity(root).add_child(button);
+    button
+}
+
+pub fn spawn_main_menu(mut commands: Commands) {
+    let root = menu_root(
+        &mut commands,
+        DespawnOnExit(MenuScreen::Main),
+        "Crystal Farm",
+    );
+    add_button(&mut commands, root, "New Game", MenuButton::New);
+    add_button(&mut commands, root, "Load Game", MenuButton::Load);
+    add_button(&mut commands, root, "Settings", MenuButton::Settings);
+    add_button(&mut commands, root, "Quit", MenuButton::Quit);
+}
+
+fn small_button(label: &str, action: MenuButton) -> impl Bundle {
+    (
+        Button,
+        action,
+        Node {
+            width: Val::Px(36.0),
+            padding: UiRect::all(Val::Px(4.0)),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        BackgroundColor(BUTTON_NORMAL),
+        children![(
+            Text::new(label),
+            TextFont {
+                font_size: 18.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        )],
+    )
+}
+
+/// Label, then `<` value `>`.
+fn add_setting_row(commands: &mut Commands, root: Entity, setting: Setting) {
+    let text = |s: &str| {
+        (
+            Text::new(s),
+            TextFont {
+                font_size: 18.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        )
+    };
+    let row = commands
+        .spawn((
+            Node {
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.0),
+                ..default()
+            },
+            children![
+                (
+                    text(setting.label()),
+                    Node {
+                        width: Val::Px(200.0),
+                        ..default()
+                    },
+                ),
+                small_button("<", MenuButton::Adjust(setting, -1)),
+                (
+                    text(""),
+                    SettingValue(setting),
+                    TextLayout::new_with_justify(Justify::Center),
+                    Node {
+                        width: Val::Px(90.0),
+                        ..default()
+                    },
+                ),
+                small_button(">", MenuButton::Adjust(setting, 1)),
+            ],
+        ))
+        .id();
+    commands.entity(root).add_child(row);
+}
+
+pub fn spawn_settings_menu(mut commands: Commands) {
+    let root = menu_root(
+        &mut commands,
+        DespawnOnExit(MenuScreen::Settings),
+        "Settings",
+    );
+    for setting in Setting::ALL {
+        add_setting_row(&mut commands, root, setting);
+    }
+    add_button(&mut commands, root, "Back", MenuButton::Back);
+}
+
+pub fn spawn_pause_menu(mut commands: Commands) {
+    let root = menu_root(&mut commands, DespawnOnExit(GameState::Paused), "Paused");
+    add_button(&mut commands, root, "Resume", MenuButton::Resume);
+    add_button(&mut commands, root, "Main Menu", MenuButton::ToMainMenu);
+    add_button(&mut commands, root, "Quit", MenuButton::Quit);
+}
+
+#[allow(clippy::type_complexity)]
+pub fn tint_buttons(
+    mut q: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<MenuButton>)>,
+) {
+    for (interaction, mut bg) in &mut q {
+        bg.0 = match interaction {
+            Interaction::Pressed => BUTTON_PRESSED,
+            Interaction::Hovered => BUTTON_HOVER,
+            Interaction::None => BUTTON_NORMAL,
+        };
+    }
+}
+
+pub fn press_buttons(
+    mut commands: Commands,
+    q: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut next_screen: ResMut<NextState<MenuScreen>>,
+    mut settings: ResMut<Settings>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    for (interaction, button) in &q {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            MenuButton::New => {
+                commands.insert_resource(StartMode::New);
+                next_state.set(GameState::Loading);
+            }
+            MenuButton::Load => {
+                commands.insert_resource(StartMode::Load);
+                next_state.set(GameState::Loading);
+            }
+            MenuButton::Settings => next_screen.set(MenuScreen::Settings),
+            MenuButton::Back => next_screen.set(MenuScreen::Main),
+            MenuButton::Resume => next_state.set(GameState::InGame),
+            MenuButton::ToMainMenu => next_state.set(GameState::MainMenu),
+            MenuButton::Quit => {
+                exit.write(AppExit::Success);
+            }
+            MenuButton::Adjust(setting, dir) => settings.adjust(*setting, *dir),
+        }
+    }
+}
+
+pub fn show_setting_values(settings: Res<Settings>, mut q: Query<(&mut Text, Ref<SettingValue>)>) {
+    for (mut text, value) in &mut q {
+        if settings.is_changed() || value.is_added() {
+            text.0 = settings.value(value.0);
+        }
+    }
+}
@@ -0,0 +1,2 @@
+﻿mod menu;
+pub use menu::*;
\ No newline at end of file
@@ -1,13 +1,19 @@
 ﻿use bevy::prelude::*;
 
+pub mod loading;
+pub mod menu;
 pub mod tooltip;
+pub mod tutorial;
 
 pub struct UIPlugin;
 
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((
+            loading::LoadingPlugin,
+            menu::MenuPlugin,
             tooltip::TooltipPlugin,
+            tutorial::TutorialPlugin,
         ));
     }
 }
\ No newline at end of file
@@ -1,8 +1,10 @@
-﻿use bevy::picking::hover::Hovered;
+﻿use std::borrow::Cow;
+
+use bevy::picking::hover::Hovered;
 use bevy::prelude::*;
 
 #[derive(Component)]
-pub struct HoverTooltip(pub &'static str);
+pub struct HoverTooltip(pub Cow<'static, str>);
 
 #[derive(Resource)]
 pub struct TooltipUi {
@@ -56,26 +58,31 @@ pub fn spawn_tooltip_ui(mut commands: Commands) {
     });
 }
 
+#[allow(clippy::type_complexity)]
 pub fn tooltip_on_hover(
     ui: Res<TooltipUi>,
     windows: Query<&Window>,
     mut panel_vis: Query<&mut Visibility>,
     mut text_q: Query<&mut Text>,
     mut node_q: Query<&mut Node>,
-    hovered_q: Query<(&Hovered, &HoverTooltip), Changed<Hovered>>,
+    hovered_q: Query<(Ref<Hovered>, &HoverTooltip), Or<(Changed<Hovered>, Changed<HoverTooltip>)>>,
 ) {
     let Ok(window) = windows.single() else { return; };
     let cursor = window.cursor_position();
 
     for (hovered, tooltip) in &hovered_q {
         let show = hovered.get();
+        // text changed on something that isn't under the cursor
+        if !show && !hovered.is_changed() {
+            continue;
+        }
 
         if let Ok(mut v) = panel_vis.get_mut(ui.panel) {
             *v = if show { Visibility::Visible } else { Visibility::Hidden };
         }
 
         if let Ok(mut text) = text_q.get_mut(ui.text) {
-            *text = Text::new(if show { tooltip.0 } else { "" });
+            *text = Text::new(if show { tooltip.0.as_ref() } else { "" });
         }
 
         if show {
@@ -0,0 +1,2 @@
+﻿mod tutorial;
+pub use tutorial::*;
\ No newline at end of file
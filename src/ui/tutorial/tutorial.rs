@@ -0,0 +1,139 @@
+﻿use bevy::prelude::*;
+
+use crate::state::{GameState, InSession};
+
+/// Text of the current tutorial step. The callout is hidden while this is `None`.
+#[derive(Resource, Default)]
+pub struct TutorialPrompt(pub Option<String>);
+
+/// UI element the tutorial is pointing at; it gets a pulsing outline.
+#[derive(Component)]
+pub struct Highlighted;
+
+#[derive(Resource)]
+pub struct TutorialUi {
+    pub panel: Entity,
+    pub text: Entity,
+}
+
+pub struct TutorialPlugin;
+
+impl Plugin for TutorialPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TutorialPrompt>()
+           .add_systems(
+               OnTransition { exited: GameState::Loading, entered: GameState::InGame },
+               spawn_tutorial_ui,
+           )
+           .add_systems(OnExit(InSession), clear_prompt)
+           .add_systems(
+               Update,
+               (
+                   show_prompt.run_if(resource_exists::<TutorialUi>),
+                   pulse_highlights,
+                   clear_highlights,
+               ),
+           );
+    }
+}
+
+pub fn spawn_tutorial_ui(mut commands: Commands) {
+    let mut text_entity = Entity::PLACEHOLDER;
+
+    // full-width row so the callout sits centred at the top of the screen
+    let panel = commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(16.0),
+                width: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            Pickable::IGNORE,
+            Visibility::Hidden,
+            DespawnOnExit(InSession),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        max_width: Val::Px(520.0),
+                        padding: UiRect::all(Val::Px(12.0)),
+                        border: UiRect::all(Val::Px(1.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(0.05, 0.08, 0.15, 0.9)),
+                    BorderColor::all(Color::srgba(0.5, 0.8, 1.0, 0.6)),
+                ))
+                .with_children(|callout| {
+                    text_entity = callout
+                        .spawn((
+                            Text::new(""),
+                            TextFont {
+                                font_size: 16.0,
+                                ..default()
+                            },
+                            TextColor(Color::WHITE),
+                        ))
+                        .id();
+                });
+        })
+        .id();
+
+    commands.insert_resource(TutorialUi {
+        panel,
+        text: text_entity,
+    });
+}
+
+/// The callout goes with the session; a stale prompt shouldn't greet the next one.
+pub fn clear_prompt(mut prompt: ResMut<TutorialPrompt>) {
+    prompt.0 = None;
+}
+
+pub fn show_prompt(
+    prompt: Res<TutorialPrompt>,
+    ui: Res<TutorialUi>,
+    mut panel_vis: Query<&mut Visibility>,
+    mut text_q: Query<&mut Text>,
+) {
+    if !prompt.is_changed() {
+        return;
+    }
+
+    if let Ok(mut v) = panel_vis.get_mut(ui.panel) {
+        *v = if prompt.0.is_some() { Visibility::Visible } else { Visibility::Hidden };
+    }
+    if let Ok(mut text) = text_q.get_mut(ui.text) {
+        *text = Text::new(prompt.0.as_deref().unwrap_or(""));
+    }
+}
+
+pub fn pulse_highlights(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut q: Query<(Entity, Option<&mut Outline>), With<Highlighted>>,
+) {
+    let glow = 0.5 + 0.5 * (time.elapsed_secs() * 4.0).sin();
+    let color = Color::srgba(1.0, 0.85, 0.2, 0.3 + 0.7 * glow);
+
+    for (e, outline) in &mut q {
+        match outline {
+            Some(mut outline) => outline.color = color,
+            None => {
+                commands
+                    .entity(e)
+                    .insert(Outline::new(Val::Px(2.0), Val::Px(3.0), color));
+            }
+        }
+    }
+}
+
+pub fn clear_highlights(mut commands: Commands, mut removed: RemovedComponents<Highlighted>) {
+    for e in removed.read() {
+        if let Ok(mut entity) = commands.get_entity(e) {
+            entity.remove::<Outline>();
+        }
+    }
+}